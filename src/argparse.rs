@@ -1,121 +1,275 @@
-#[derive(Debug)]
+/// When to colorize the output, set by -R.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    Always,
+    /// colorize when writing to a terminal, unless NO_COLOR or CLICOLOR_FORCE say otherwise.
+    #[default]
+    Auto,
+    Never,
+}
+
+/// Size suffixes accepted by `parse_number`, longest first so that `KiB` isn't taken for `B`.
+const SIZE_SUFFIXES: [(&str, u64); 14] = [
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+    ("TiB", 1 << 40),
+    ("kB", 1_000),
+    ("KB", 1_000),
+    ("MB", 1_000_000),
+    ("GB", 1_000_000_000),
+    ("TB", 1_000_000_000_000),
+    ("k", 1 << 10),
+    ("K", 1 << 10),
+    ("M", 1 << 20),
+    ("G", 1 << 30),
+    ("T", 1 << 40),
+];
+
+/// The most octets per line -c accepts, like xxd.
+const MAX_COLUMNS: u64 = 256;
+
+/// Parses an unsigned number given on the command line.
+///
+/// Numbers can be decimal, hex (0x), octal (0o, or a leading 0 like in C) or binary (0b),
+/// and can be followed by a size suffix: k, M, G and T (or KiB, MiB, GiB and TiB) multiply
+/// by powers of 1024, while kB, MB, GB and TB multiply by powers of 1000.
+pub fn parse_number(text: &str) -> Result<u64, String> {
+    if text.is_empty() {
+        return Err("expected a number but found nothing.".to_owned());
+    }
+
+    let (digits, multiplier) = SIZE_SUFFIXES
+        .iter()
+        .find_map(|(suffix, multiplier)| {
+            text.strip_suffix(suffix)
+                .filter(|digits| !digits.is_empty())
+                .map(|digits| (digits, *multiplier))
+        })
+        .unwrap_or((text, 1));
+
+    let (digits, radix, radix_name) = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        (hex, 16, "hex")
+    } else if let Some(octal) = digits
+        .strip_prefix("0o")
+        .or_else(|| digits.strip_prefix("0O"))
+    {
+        (octal, 8, "octal")
+    } else if let Some(binary) = digits
+        .strip_prefix("0b")
+        .or_else(|| digits.strip_prefix("0B"))
+    {
+        (binary, 2, "binary")
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        (&digits[1..], 8, "octal")
+    } else {
+        (digits, 10, "decimal")
+    };
+
+    if digits.is_empty() {
+        return Err(format!("{} has no digits after its prefix.", text));
+    }
+
+    let mut value: u64 = 0;
+    for (i, c) in digits.char_indices() {
+        let digit = match c.to_digit(radix) {
+            Some(digit) => digit,
+            None if radix == 10 && digits[i..].chars().all(|c| c.is_ascii_alphabetic()) => {
+                return Err(format!(
+                    "{} has an unknown size suffix '{}' (expected k, M, G, T, KiB, MiB, GiB, TiB, kB, MB, GB or TB).",
+                    text,
+                    &digits[i..]
+                ));
+            }
+            None => {
+                return Err(format!(
+                    "'{}' is not a valid {} digit in {}.",
+                    c, radix_name, text
+                ));
+            }
+        };
+
+        value = value
+            .checked_mul(radix as u64)
+            .and_then(|value| value.checked_add(digit as u64))
+            .ok_or_else(|| format!("{} is too large.", text))?;
+    }
+
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("{} is too large.", text))
+}
+
+/// Parses a number given on the command line like `parse_number`, allowing a leading
+/// '+' or '-'.
+pub fn parse_signed_number(text: &str) -> Result<i64, String> {
+    let (negative, magnitude) = match text.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+
+    let magnitude = parse_number(magnitude)?;
+    if negative {
+        0i64.checked_sub_unsigned(magnitude)
+    } else {
+        i64::try_from(magnitude).ok()
+    }
+    .ok_or_else(|| format!("{} is too large.", text))
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct Options {
-    /// TODO toggles autoskip. A single '*' replaces NUL-lines.
+    /// toggles autoskip. A single '*' replaces NUL-lines.
     pub autoskip: bool,
+    /// Like autoskip, but a single '*' replaces any run of identical lines.
+    pub squeeze: bool,
     /// Switches to binary dump instead of hex dump.
     pub bits: bool,
+    /// Write the bits of each byte least significant bit first, when using -b.
+    pub bits_lsb_first: bool,
+    /// Separate the two nibbles of each byte with a '_', when using -b.
+    pub nibble_separator: bool,
     /// Specifies the number of columns in the hex dump.
     pub cols: Option<usize>,
+    /// Show offsets in decimal instead of hex.
+    pub decimal_offset: bool,
     /// Capitalize variable names in C include file style, when using -i
     pub capitalize: bool,
     /// Switch to little-endian hex dump.
     pub is_little_endian: bool,
-    /// the size of a group of bytes in the hexdump. Default is 2.
-    pub group_size: usize,
+    /// the size of a group of bytes in the hexdump. Default is 2 (-e: 4, -b: 1).
+    pub group_size: Option<usize>,
     /// Display help and exit.
     pub display_help: bool,
     /// Output result in C include file style.
     pub include_format: bool,
     /// Stop after writing <len_octets> octets.
-    pub len_octets: Option<usize>,
+    pub len_octets: Option<u64>,
     /// Override the variable name output when -i is used. The array is named <include_name>.
     pub include_name: Option<String>,
-    /// Add <offset> to the displayed file position.
-    pub offset: usize,
+    /// Add <offset> to the displayed file position. Negative offsets wrap around.
+    pub offset: i64,
     /// Output in PostScript continuous hex dump style. Also known as plain hex dump style.
     pub postscript_style: bool,
-    /// TODO Convert hex dump to binary.
+    /// Convert hex dump to binary.
     pub revert: bool,
+    /// When reverting, stop at the first malformed line instead of skipping over it.
+    pub strict: bool,
     /// Start at <seek> bytes.
-    pub seek: i32,
+    pub seek: i64,
+    /// Seek relative to the current position in the input instead of its start or end, set
+    /// by a leading '+' on <seek>.
+    pub seek_relative: bool,
     /// Use upper-case hex letters.
     pub uppercase: bool,
+    /// When to colorize the output.
+    pub color: ColorMode,
     /// Display version number and exit.
     pub display_version: bool,
+    /// Dump every file given on the command line, each after a `==> name <==` header.
+    pub multiple_files: bool,
+    /// With multiple files, continue the offsets of each file from where the last one
+    /// ended, as if the files were one stream.
+    pub continue_offsets: bool,
+    /// Read input files through a memory mapping instead of copying them into a buffer.
+    pub mmap: bool,
     //// Input file to read from.
     pub infile: Option<String>,
+    /// Input files to read from, when dumping multiple files.
+    pub infiles: Vec<String>,
     /// Output file to write to.
     pub outfile: Option<String>,
 }
 
 impl Options {
-    pub fn default() -> Self {
-        Options {
-            autoskip: false,
-            bits: false,
-            cols: None,
-            capitalize: false,
-            is_little_endian: false,
-            group_size: 2,
-            display_help: false,
-            include_format: false,
-            len_octets: None,
-            include_name: None,
-            offset: 0,
-            postscript_style: false,
-            revert: false,
-            seek: 0,
-            uppercase: false,
-            display_version: false,
-            infile: None,
-            outfile: None,
-        }
+    /// The number of octets per group: -g if it was given, otherwise 1 for -b, 4 for -e
+    /// and 2 for everything else.
+    pub fn group_size(&self) -> usize {
+        self.group_size.unwrap_or(if self.bits {
+            1
+        } else if self.is_little_endian {
+            4
+        } else {
+            2
+        })
     }
 
     /// Parses a list of arguments from the command line and returns Options.
     /// Grammmar:
     /// [binary-name] [-r[evert]] [options] [[infile] [outfile]]
+    /// [binary-name] -m [options] [infile...]
     pub fn parse_options(arguments: Vec<String>) -> Result<Self, String> {
         let mut options = Options::default();
         let mut arg: usize = 0;
 
         /// Get the next argument in the list and check if it is a number.
         /// If the next argument doesn't exist or if we can't parse the argument, error out.
-        fn take<T: std::str::FromStr>(arguments: &Vec<String>, arg: &usize) -> Option<T> {
+        fn take<T: std::str::FromStr>(arguments: &[String], arg: &usize) -> Option<T> {
             arguments
                 .get(arg + 1)
                 .and_then(|next_arg| next_arg.parse::<T>().ok())
         }
 
+        /// Get the next argument in the list and parse it with `parse_number`.
+        /// If the next argument doesn't exist or isn't a number, error out.
+        fn take_number(arguments: &[String], arg: &usize, name: &str) -> Result<u64, String> {
+            match arguments.get(arg + 1) {
+                None => Err(format!("{} requires an integer value following it.", name)),
+                Some(next_arg) => parse_number(next_arg).map_err(|e| format!("{}: {}", name, e)),
+            }
+        }
+
+        /// Like `take_number`, but allows the number to be negative.
+        fn take_signed_number(
+            arguments: &[String],
+            arg: &usize,
+            name: &str,
+        ) -> Result<i64, String> {
+            match arguments.get(arg + 1) {
+                None => Err(format!("{} requires an integer value following it.", name)),
+                Some(next_arg) => {
+                    parse_signed_number(next_arg).map_err(|e| format!("{}: {}", name, e))
+                }
+            }
+        }
+
         while arg < arguments.len() {
             let argument = arguments.get(arg).expect("this cannot fail");
             if argument.starts_with("-") {
                 match argument.as_str() {
                     "-a" | "-autoskip" => options.autoskip = true,
+                    "-S" | "-squeeze" => options.squeeze = true,
                     "-b" | "-bits" => options.bits = true,
-                    "-c" | "-cols" => match take(&arguments, &arg) {
-                        None => {
-                            return Err("-cols requires an integer value following it.".to_owned());
+                    "-c" | "-cols" => {
+                        let cols = take_number(&arguments, &arg, "-cols")?;
+                        if cols > MAX_COLUMNS {
+                            return Err(format!(
+                                "-cols: {} is more than the maximum of {} columns.",
+                                cols, MAX_COLUMNS
+                            ));
                         }
-                        Some(cols) => {
-                            options.cols = Some(std::cmp::max(1, cols));
-                            arg += 1;
-                        }
-                    },
+                        options.cols = Some(cols.max(1) as usize);
+                        arg += 1;
+                    }
                     "-C" | "-capitalize" => options.capitalize = true,
+                    "-d" => options.decimal_offset = true,
                     "-e" => options.is_little_endian = true,
-                    "-g" | "-groupsize" => match take::<usize>(&arguments, &arg) {
-                        None => {
-                            return Err(
-                                "-groupsize requires an integer value following it.".to_owned()
-                            );
-                        }
-                        Some(g) => {
-                            options.group_size = g.clamp(1, 16); //std::cmp::max(1, std::cmp::min(16, g));
-                            arg += 1;
-                        }
-                    },
+                    "-lsb" => options.bits_lsb_first = true,
+                    "-nibbles" => options.nibble_separator = true,
+                    "-g" | "-groupsize" => {
+                        let g = take_number(&arguments, &arg, "-groupsize")?;
+                        options.group_size = Some(g.clamp(1, 16) as usize); //std::cmp::max(1, std::cmp::min(16, g));
+                        arg += 1;
+                    }
                     "-h" | "-help" => options.display_help = true,
                     "-i" | "-include" => options.include_format = true,
-                    "-l" | "-len" => match take(&arguments, &arg) {
-                        None => {
-                            return Err("-len requires an integer value following it.".to_owned());
-                        }
-                        Some(len_octets) => {
-                            options.len_octets = Some(len_octets);
-                            arg += 1;
-                        }
-                    },
+                    "-l" | "-len" => {
+                        options.len_octets = Some(take_number(&arguments, &arg, "-len")?);
+                        arg += 1;
+                    }
                     "-n" | "-name" => match take(&arguments, &arg) {
                         None => {
                             return Err("-name requires an array name following it.".to_owned());
@@ -125,34 +279,45 @@ impl Options {
                             arg += 1;
                         }
                     },
-                    "-o" => match take(&arguments, &arg) {
-                        None => return Err("-o requires an integer value following it.".to_owned()),
-                        Some(offset) => {
-                            options.offset = offset;
-                            arg += 1;
-                        }
-                    },
+                    "-o" | "-offset" => {
+                        options.offset = take_signed_number(&arguments, &arg, "-o")?;
+                        arg += 1;
+                    }
                     "-p" | "-ps" | "-postscript" | "-plain" => options.postscript_style = true,
                     "-r" | "-revert" => options.revert = true,
-                    "-seek" => match take(&arguments, &arg) {
-                        None => {
-                            return Err(
-                                "-seek requires an unsigned offset following it.".to_owned()
-                            );
+                    "-strict" | "--strict" => options.strict = true,
+                    "-s" | "-seek" => {
+                        options.seek = take_signed_number(&arguments, &arg, argument)?;
+                        options.seek_relative = arguments[arg + 1].starts_with('+');
+                        arg += 1;
+                    }
+                    "-u" => options.uppercase = true,
+                    "-R" | "-color" => match take::<String>(&arguments, &arg).as_deref() {
+                        Some("always") => {
+                            options.color = ColorMode::Always;
+                            arg += 1;
                         }
-                        Some(offset) => options.seek = offset,
-                    },
-                    "-s" => match take(&arguments, &arg) {
-                        None => {
-                            return Err("-s requires an integer offset following it.".to_owned());
+                        Some("auto") => {
+                            options.color = ColorMode::Auto;
+                            arg += 1;
                         }
-                        Some(offset) => {
-                            options.seek = offset;
+                        Some("never") => {
+                            options.color = ColorMode::Never;
                             arg += 1;
                         }
+                        _ => {
+                            return Err(
+                                "-R requires one of always, auto or never following it.".to_owned()
+                            );
+                        }
                     },
-                    "-u" => options.uppercase = true,
                     "-v" => options.display_version = true,
+                    "-m" | "-multi" => options.multiple_files = true,
+                    "-continue" => {
+                        options.multiple_files = true;
+                        options.continue_offsets = true;
+                    }
+                    "-mmap" => options.mmap = true,
                     option => return Err(format!("{} is not an option.", option)),
                 }
             } else {
@@ -161,6 +326,14 @@ impl Options {
             arg += 1;
         }
 
+        if options.multiple_files {
+            if options.revert {
+                return Err("-m cannot be used with -r.".to_owned());
+            }
+            options.infiles = arguments[arg..].to_vec();
+            return Ok(options);
+        }
+
         if arg < arguments.len() {
             options.infile = Some(arguments.get(arg).expect("this cannot fail").clone());
             arg += 1;
@@ -173,3 +346,94 @@ impl Options {
         Ok(options)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn parse_number_reads_prefixes() {
+        assert_eq!(parse_number("42"), Ok(42));
+        assert_eq!(parse_number("0x2A"), Ok(42));
+        assert_eq!(parse_number("0o52"), Ok(42));
+        assert_eq!(parse_number("052"), Ok(42));
+        assert_eq!(parse_number("0b101010"), Ok(42));
+        assert_eq!(parse_number("0"), Ok(0));
+    }
+
+    #[test]
+    fn parse_number_reads_size_suffixes() {
+        assert_eq!(parse_number("2k"), Ok(2048));
+        assert_eq!(parse_number("2KiB"), Ok(2048));
+        assert_eq!(parse_number("2kB"), Ok(2000));
+        assert_eq!(parse_number("0x10M"), Ok(16 << 20));
+        assert_eq!(parse_number("1T"), Ok(1 << 40));
+    }
+
+    #[test]
+    fn parse_number_explains_bad_numbers() {
+        assert_eq!(
+            parse_number(""),
+            Err("expected a number but found nothing.".to_owned())
+        );
+        assert_eq!(
+            parse_number("0x"),
+            Err("0x has no digits after its prefix.".to_owned())
+        );
+        assert_eq!(
+            parse_number("08"),
+            Err("'8' is not a valid octal digit in 08.".to_owned())
+        );
+        assert_eq!(
+            parse_number("12q"),
+            Err("12q has an unknown size suffix 'q' (expected k, M, G, T, KiB, MiB, GiB, TiB, kB, MB, GB or TB).".to_owned())
+        );
+        assert_eq!(
+            parse_number("16E"),
+            Err("16E has an unknown size suffix 'E' (expected k, M, G, T, KiB, MiB, GiB, TiB, kB, MB, GB or TB).".to_owned())
+        );
+        assert_eq!(
+            parse_number("18446744073709551616"),
+            Err("18446744073709551616 is too large.".to_owned())
+        );
+        assert_eq!(
+            parse_number("16777216T"),
+            Err("16777216T is too large.".to_owned())
+        );
+    }
+
+    #[test]
+    fn parse_signed_number_reads_signs() {
+        assert_eq!(parse_signed_number("+0x10"), Ok(16));
+        assert_eq!(parse_signed_number("-1k"), Ok(-1024));
+        assert_eq!(parse_signed_number("-9223372036854775808"), Ok(i64::MIN));
+        assert_eq!(
+            parse_signed_number("9223372036854775808"),
+            Err("9223372036854775808 is too large.".to_owned())
+        );
+    }
+
+    #[test]
+    fn seek_remembers_a_leading_plus() {
+        let options = Options::parse_options(arguments("-s +0x10 file")).unwrap();
+        assert_eq!(options.seek, 16);
+        assert!(options.seek_relative);
+        assert_eq!(options.infile.as_deref(), Some("file"));
+    }
+
+    #[test]
+    fn cols_is_capped() {
+        assert_eq!(
+            Options::parse_options(arguments("-c 1T")).unwrap_err(),
+            "-cols: 1099511627776 is more than the maximum of 256 columns."
+        );
+        assert_eq!(
+            Options::parse_options(arguments("-c 0x100")).unwrap().cols,
+            Some(256)
+        );
+    }
+}
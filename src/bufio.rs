@@ -1,55 +1,291 @@
-/// A buffered reader that can only ingest a limited amount of bytes from the provided handle
+use crate::RxdError;
+use crate::mmap::Mapping;
+
+/// Where a `LimitedBufReader` gets its bytes from.
+pub enum Input {
+    /// a handle that is read into the reader's buffer, like stdin or a pipe
+    Stream(Box<dyn std::io::Read>),
+    /// a memory-mapped file, which is formatted straight from the mapping
+    Mapped {
+        mapping: Mapping,
+        /// the position in the mapping that the next read starts at
+        position: usize,
+    },
+}
+
+impl Input {
+    /// Memory-maps `file`, starting at `position`, which is where the file has been seeked
+    /// to. Falls back to reading from `file` if it can't be mapped.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may write to or truncate `file` while the returned input is alive, as for
+    /// `Mapping::map`.
+    pub unsafe fn from_file(file: std::fs::File, position: u64) -> Input {
+        // SAFETY: passed on to our caller.
+        match unsafe { Mapping::map(&file) } {
+            Ok(mapping) => Input::Mapped {
+                // the file can't be seeked past the end of what we can map
+                position: usize::try_from(position)
+                    .unwrap_or(usize::MAX)
+                    .min(mapping.len()),
+                mapping,
+            },
+            Err(_) => Input::Stream(Box::new(file)),
+        }
+    }
+}
+
+impl std::io::Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Input::Stream(handle) => handle.read(buf),
+            Input::Mapped { mapping, position } => {
+                let mut remaining = &mapping.as_ref()[*position..];
+                let bytes_read = remaining.read(buf)?;
+                *position += bytes_read;
+                Ok(bytes_read)
+            }
+        }
+    }
+}
+
+/// A buffered reader that can only ingest a limited amount of bytes from the provided input
 pub struct LimitedBufReader {
-    // the buffer where reads are being stored
+    // the buffer where reads are being stored. Its length never changes.
     buffer: Vec<u8>,
-    /// the handle that BufReader is reading from
-    handle: Box<dyn std::io::Read>,
+    /// the input that BufReader is reading from
+    input: Input,
+    /// the part of the buffer, or of the mapping when `input` is mapped, that holds the
+    /// bytes of the last read
+    valid: std::ops::Range<usize>,
+    /// an error hit after part of the buffer was filled, kept until those bytes are used
+    pending_error: Option<std::io::Error>,
     /// total number of bytes that BufReader has already read
-    bytes_read: usize,
+    bytes_read: u64,
     /// the total number of bytes that BufReader can read
-    limit: Option<usize>,
+    limit: Option<u64>,
 }
 
 impl LimitedBufReader {
-    pub fn new(buf_size: usize, handle: Box<dyn std::io::Read>, limit: Option<usize>) -> Self {
-        let mut buffer: Vec<u8> = Vec::new();
-        buffer.resize(buf_size, 0);
+    /// Creates a reader over `input`. `buf_size` is the size of the buffer streams are read
+    /// into; a mapped input is returned in one piece, without copying.
+    pub fn new(buf_size: usize, input: Input, limit: Option<u64>) -> Self {
+        let buffer: Vec<u8> = match input {
+            Input::Stream(_) => vec![0; buf_size.max(1)],
+            Input::Mapped { .. } => Vec::new(),
+        };
         LimitedBufReader {
             buffer,
-            handle,
+            input,
+            valid: 0..0,
+            pending_error: None,
             bytes_read: 0,
             limit,
         }
     }
 
-    pub fn read(&mut self) -> Result<usize, std::io::Error> {
-        if let Some(limit) = self.limit {
-            if self.bytes_read >= limit {
-                return Ok(0);
+    /// shortens a read of `len` bytes so that it stops at the limit.
+    fn clamp_to_limit(&self, len: usize) -> usize {
+        match self.limit {
+            // the remaining limit only matters when it's less than a usize
+            Some(limit) => len.min((limit - self.bytes_read).min(usize::MAX as u64) as usize),
+            None => len,
+        }
+    }
+
+    /// Reads the next part of the input, returning its length, or 0 at the end of the input
+    /// or the limit. On failure, the error says how far into the input reading got.
+    ///
+    /// Streams fill the whole buffer unless the input or the limit ends first, however
+    /// the input happens to be delivered, so each read is a whole number of rows.
+    pub fn read(&mut self) -> Result<usize, RxdError> {
+        if let Some(limit) = self.limit
+            && self.bytes_read >= limit
+        {
+            return Ok(0);
+        }
+
+        if let Some(error) = self.pending_error.take() {
+            return Err(RxdError::ReadError {
+                offset: self.bytes_read,
+                error,
+            });
+        }
+
+        let wanted = match self.input {
+            Input::Stream(_) => self.clamp_to_limit(self.buffer.len()),
+            Input::Mapped {
+                ref mapping,
+                position,
+            } => self.clamp_to_limit(mapping.len() - position),
+        };
+
+        let handle = match self.input {
+            Input::Stream(ref mut handle) => handle,
+            Input::Mapped {
+                ref mut position, ..
+            } => {
+                self.valid = *position..*position + wanted;
+                *position += wanted;
+                self.bytes_read += wanted as u64;
+                return Ok(wanted);
+            }
+        };
+
+        let mut filled = 0;
+        while filled < wanted {
+            match handle.read(&mut self.buffer[filled..wanted]) {
+                Ok(0) => break,
+                Ok(bytes_read) => filled += bytes_read,
+                // a signal arrived before anything was read, so nothing was lost
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                // hand out what was read before the error, and report it on the next read
+                Err(error) if filled > 0 => {
+                    self.pending_error = Some(error);
+                    break;
+                }
+                Err(error) => {
+                    return Err(RxdError::ReadError {
+                        offset: self.bytes_read,
+                        error,
+                    });
+                }
+            }
+        }
+
+        self.valid = 0..filled;
+        self.bytes_read += filled as u64;
+        Ok(filled)
+    }
+
+    pub fn total_bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+}
+
+impl AsRef<[u8]> for LimitedBufReader {
+    /// the bytes of the last read.
+    fn as_ref(&self) -> &[u8] {
+        match self.input {
+            Input::Stream(_) => &self.buffer[self.valid.clone()],
+            Input::Mapped { ref mapping, .. } => &mapping.as_ref()[self.valid.clone()],
+        }
+    }
+}
+
+/// Reads and throws away up to `count` bytes from `handle`, for seeking forwards in input
+/// that can't seek, like a pipe. Returns the number of bytes skipped, which is less than
+/// `count` if the input ended first.
+pub fn skip_bytes(handle: &mut dyn std::io::Read, count: u64) -> std::io::Result<u64> {
+    std::io::copy(
+        &mut std::io::Read::take(handle, count),
+        &mut std::io::sink(),
+    )
+}
+
+/// Reads `handle` to the end and keeps only its last `count` bytes, for seeking backwards
+/// from the end of input that can't seek. Returns those bytes along with their offset in
+/// the input.
+pub fn tail_bytes(handle: &mut dyn std::io::Read, count: u64) -> std::io::Result<(Vec<u8>, u64)> {
+    // a ring buffer holding the last `count` bytes seen, oldest at `start`
+    let mut ring: Vec<u8> = Vec::new();
+    let mut start: usize = 0;
+    let mut total: u64 = 0;
+    let mut chunk = vec![0; 64 * 1024];
+
+    loop {
+        let bytes_read = match handle.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(bytes_read) => bytes_read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        total += bytes_read as u64;
+
+        for &byte in &chunk[..bytes_read] {
+            if (ring.len() as u64) < count {
+                ring.push(byte);
+            } else if count > 0 {
+                ring[start] = byte;
+                start = (start + 1) % ring.len();
             }
         }
+    }
 
-        let bytes_read = self.handle.read(&mut self.buffer).unwrap_or(0);
+    ring.rotate_left(start);
+    let offset = total - ring.len() as u64;
+    Ok((ring, offset))
+}
 
-        if let Some(limit) = self.limit {
-            let bytes_remaining = limit - self.bytes_read;
-            if bytes_read > bytes_remaining {
-                self.bytes_read += bytes_remaining;
-                self.buffer.truncate(bytes_remaining);
-                return Ok(bytes_remaining);
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// hands out its bytes a few at a time, 1 to 7 bytes per read, like a slow pipe.
+    pub(crate) struct Trickle {
+        data: Vec<u8>,
+        position: usize,
+        reads: usize,
+    }
+
+    impl Trickle {
+        pub(crate) fn new(data: &[u8]) -> Self {
+            Trickle {
+                data: data.to_vec(),
+                position: 0,
+                reads: 0,
             }
         }
+    }
 
-        self.buffer.truncate(bytes_read);
-        self.bytes_read += bytes_read;
-        Ok(bytes_read)
+    impl std::io::Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reads += 1;
+            let len = (self.reads % 7 + 1)
+                .min(buf.len())
+                .min(self.data.len() - self.position);
+            buf[..len].copy_from_slice(&self.data[self.position..self.position + len]);
+            self.position += len;
+            Ok(len)
+        }
     }
 
-    pub fn as_ref(&self) -> &Vec<u8> {
-        return self.buffer.as_ref();
+    fn data() -> Vec<u8> {
+        (0..10_000u32).map(|i| (i * 7 % 251) as u8).collect()
     }
 
-    pub fn total_bytes_read(&self) -> usize {
-        self.bytes_read
+    #[test]
+    fn read_fills_the_buffer_from_short_reads() {
+        let data = data();
+        let input = Input::Stream(Box::new(Trickle::new(&data)));
+        let mut reader = LimitedBufReader::new(4096, input, Some(9000));
+
+        let mut seen = Vec::new();
+        let mut lengths = Vec::new();
+        loop {
+            let bytes_read = reader.read().unwrap();
+            if bytes_read == 0 {
+                break;
+            }
+            lengths.push(bytes_read);
+            seen.extend_from_slice(reader.as_ref());
+        }
+        assert_eq!(lengths, [4096, 4096, 808]);
+        assert_eq!(seen, &data[..9000]);
+    }
+
+    #[test]
+    fn tail_bytes_does_not_depend_on_how_reads_are_split() {
+        let data = data();
+        for count in [0, 1, 5, 4099, 10_000, 20_000] {
+            let whole = tail_bytes(&mut std::io::Cursor::new(&data), count).unwrap();
+            let trickled = tail_bytes(&mut Trickle::new(&data), count).unwrap();
+            assert_eq!(trickled, whole, "tail of {count} bytes");
+
+            let kept = data.len().min(count as usize);
+            assert_eq!(whole.0, &data[data.len() - kept..]);
+            assert_eq!(whole.1, (data.len() - kept) as u64);
+        }
     }
 }
@@ -1,4 +1,5 @@
-use std::io::Write;
+use std::borrow::Cow;
+use std::io::{self, Write};
 
 const BYTE_MAP: [&str; 256] = [
     "00", "01", "02", "03", "04", "05", "06", "07", "08", "09", "0a", "0b", "0c", "0d", "0e", "0f",
@@ -39,22 +40,37 @@ const UPPER_BYTE_MAP: [&str; 256] = [
 ];
 
 /// Write byte in lower-hex, little-endian format to the string provided.
-pub fn to_lower_hex(buffer: &mut dyn Write, byte: &u8) {
+pub fn to_lower_hex(buffer: &mut dyn Write, byte: &u8) -> io::Result<()> {
     let bytes = BYTE_MAP[*byte as usize].as_bytes();
-    buffer.write(bytes).expect("Write must succeed.");
+    buffer.write_all(bytes)
 }
 
 /// Write byte in upper-hex, little-endian format to the string provided.
-pub fn to_upper_hex(buffer: &mut dyn Write, byte: &u8) {
+pub fn to_upper_hex(buffer: &mut dyn Write, byte: &u8) -> io::Result<()> {
     let bytes = UPPER_BYTE_MAP[*byte as usize].as_bytes();
-    buffer.write(bytes).expect("Write must succeed.");
+    buffer.write_all(bytes)
 }
 
-/// Write byte in binary format to the string provided.
-pub fn to_binary(buffer: &mut dyn Write, byte: &u8) {
-    buffer
-        .write_fmt(format_args!("{:b}{:b}", byte & 15, byte >> 4 & 15))
-        .expect("Write must succeed.");
+/// Write byte in binary format, most significant bit first, to the string provided.
+pub fn to_binary(buffer: &mut dyn Write, byte: &u8) -> io::Result<()> {
+    buffer.write_fmt(format_args!("{:08b}", byte))
+}
+
+/// Write byte in binary format, least significant bit first, to the string provided.
+pub fn to_binary_lsb(buffer: &mut dyn Write, byte: &u8) -> io::Result<()> {
+    to_binary(buffer, &byte.reverse_bits())
+}
+
+/// Write byte in binary format, most significant bit first, with a '_' between the
+/// two nibbles to the string provided.
+pub fn to_binary_nibbles(buffer: &mut dyn Write, byte: &u8) -> io::Result<()> {
+    buffer.write_fmt(format_args!("{:04b}_{:04b}", byte >> 4, byte & 15))
+}
+
+/// Write byte in binary format, least significant bit first, with a '_' between the
+/// two nibbles to the string provided.
+pub fn to_binary_lsb_nibbles(buffer: &mut dyn Write, byte: &u8) -> io::Result<()> {
+    to_binary_nibbles(buffer, &byte.reverse_bits())
 }
 
 pub enum Color {
@@ -66,23 +82,29 @@ pub enum Color {
     Magenta,
     Cyan,
     White,
+    /// A color from the 256-color palette.
+    Ansi256(u8),
+    /// A 24-bit (truecolor) color.
+    Rgb(u8, u8, u8),
     Reset,
     Bold,
 }
 
 impl Color {
-    pub fn ansi(&self) -> &'static str {
+    pub fn ansi(&self) -> Cow<'static, str> {
         match self {
-            Color::Black => "\u{1b}[90m",
-            Color::Red => "\u{1b}[91m",
-            Color::Green => "\u{1b}[92m",
-            Color::Yellow => "\u{1b}[93m",
-            Color::Blue => "\u{1b}[94m",
-            Color::Magenta => "\u{1b}[95m",
-            Color::Cyan => "\u{1b}[96m",
-            Color::White => "\u{1b}[97m",
-            Color::Reset => "\u{1b}[0m",
-            Color::Bold => "\u{1b}[1m",
+            Color::Black => "\u{1b}[90m".into(),
+            Color::Red => "\u{1b}[91m".into(),
+            Color::Green => "\u{1b}[92m".into(),
+            Color::Yellow => "\u{1b}[93m".into(),
+            Color::Blue => "\u{1b}[94m".into(),
+            Color::Magenta => "\u{1b}[95m".into(),
+            Color::Cyan => "\u{1b}[96m".into(),
+            Color::White => "\u{1b}[97m".into(),
+            Color::Ansi256(index) => format!("\u{1b}[38;5;{}m", index).into(),
+            Color::Rgb(r, g, b) => format!("\u{1b}[38;2;{};{};{}m", r, g, b).into(),
+            Color::Reset => "\u{1b}[0m".into(),
+            Color::Bold => "\u{1b}[1m".into(),
         }
     }
 }
@@ -1,8 +1,13 @@
 pub mod argparse;
 pub mod bufio;
 pub mod format;
+pub mod mmap;
 pub mod revert;
-use crate::format::{Color, to_binary, to_lower_hex, to_upper_hex};
+pub mod theme;
+use crate::format::{
+    Color, to_binary, to_binary_lsb, to_binary_lsb_nibbles, to_binary_nibbles, to_lower_hex,
+    to_upper_hex,
+};
 use std::io::{IsTerminal, Seek, SeekFrom, Write};
 use std::{env, fs};
 
@@ -10,6 +15,28 @@ use std::{env, fs};
 pub enum RxdError {
     Message(String),
     IoError(std::io::Error),
+    /// Bad command line options.
+    Usage(String),
+    /// The input file couldn't be opened.
+    InputError(String),
+    /// The output file couldn't be opened.
+    OutputError(String),
+    /// Seeking in the input or the output failed.
+    SeekError(String),
+    /// A hex dump that can't be reverted, outside of the line-by-line checks of strict mode.
+    InvalidDump(String),
+    /// A malformed hex dump found while reverting in strict mode.
+    ParseError {
+        line: usize,
+        column: usize,
+        text: String,
+        reason: String,
+    },
+    /// Reading the input failed `offset` bytes in.
+    ReadError {
+        offset: u64,
+        error: std::io::Error,
+    },
 }
 
 impl From<std::io::Error> for RxdError {
@@ -18,11 +45,45 @@ impl From<std::io::Error> for RxdError {
     }
 }
 
+impl RxdError {
+    /// The status rxd exits with after this error. 1 to 4 mean what they do for xxd: 1 is
+    /// for usage errors, 2 for the input, 3 for the output and 4 for an unreachable seek
+    /// position. Past those, 6 is for other failed reads and writes and 7 for a hex dump
+    /// that can't be reverted.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RxdError::Message(_) | RxdError::Usage(_) => 1,
+            RxdError::InputError(_) => 2,
+            RxdError::OutputError(_) => 3,
+            RxdError::SeekError(_) => 4,
+            RxdError::IoError(_) | RxdError::ReadError { .. } => 6,
+            RxdError::ParseError { .. } | RxdError::InvalidDump(_) => 7,
+        }
+    }
+}
+
 impl std::fmt::Display for RxdError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RxdError::Message(s) => write!(f, "Error: {s}"),
+            RxdError::Message(s)
+            | RxdError::Usage(s)
+            | RxdError::InputError(s)
+            | RxdError::OutputError(s)
+            | RxdError::SeekError(s)
+            | RxdError::InvalidDump(s) => write!(f, "Error: {s}"),
             RxdError::IoError(e) => write!(f, "Error: {e}"),
+            RxdError::ParseError {
+                line,
+                column,
+                text,
+                reason,
+            } => write!(f, "Error: line {line}, column {column}: {reason}: '{text}'"),
+            RxdError::ReadError { offset, error } => {
+                write!(
+                    f,
+                    "Error: could not read the input at offset {offset:#x}: {error}"
+                )
+            }
         }
     }
 }
@@ -33,39 +94,63 @@ const HELP_TEXT: &str = "
 Usage:
        xxd [options] [infile [outfile]]
     or
-       xxd -r [-s [-]offset] [-c cols] [-ps] [infile [outfile]]
+       xxd -r [-s [-]offset] [-c cols] [-ps] [-strict] [infile [outfile]]
+    or
+       xxd -m [options] [infile...]
 Options:
     -a          toggle autoskip: A single '*' replaces nul-lines. Default off.
+    -S          squeeze: A single '*' replaces any run of identical lines. Default off.
     -b          binary digit dump (incompatible with -ps,-i). Default hex.
+    -lsb        write bits least significant bit first (-b).
+    -nibbles    separate the nibbles of each byte with '_' (-b).
     -C          capitalize variable names in C include file style (-i).
     -c cols     format <cols> octets per line. Default 16 (-i: 12, -ps: 30).
-    -e          little-endian dump (incompatible with -ps,-i,-r).
-    -g bytes    number of octets per group in normal output. Default 2 (-e: 4).
+    -e          little-endian dump (incompatible with -ps,-i).
+    -g bytes    number of octets per group in normal output. Default 2 (-e: 4, -b: 1).
     -h          print this summary.
     -i          output in C include file style.
     -l len      stop after <len> octets.
+    -m          dump every infile, each after a '==> infile <==' header. Writes to stdout.
+    -continue   like -m, continuing offsets from one infile to the next.
+    -mmap       memory-map infiles instead of reading them. Faster for large files, but
+                rxd dies (SIGBUS) if a file is truncated while it is being dumped.
     -n name     set the variable name used in C include output (-i).
     -o off      add <off> to the displayed file position.
     -ps         output in postscript plain hexdump style.
     -r          reverse operation: convert (or patch) hexdump into binary.
     -r -s off   revert with <off> added to file positions found in hexdump.
+    -r -strict  stop at the first malformed line, before writing anything.
     -d          show offset in decimal instead of hex.
     -s [+][-]seek  start at <seek> bytes abs. (or +: rel.) infile offset.
     -u          use upper case hex letters.
     -R when     colorize the output; <when> can be 'always', 'auto' or 'never'. Default: 'auto'.
+                Colors come from $RXD_THEME (a theme, 'default' or 'colorblind'),
+                $RXD_THEME_FILE or ~/.config/rxd/theme.
     -v          show version: \"rxd 2025-10 by Krishna Sivakumar\".
+Exit status:
+    0 success, 1 bad options, 2 input file error, 3 output file error,
+    4 seek position unreachable, 6 read or write error, 7 hex dump can't be reverted.
 ";
 
 const VERSION: &str = "rxd 2025-10 by Krishna Sivakumar";
 
-/// prints bytes read from `inhandle` to `outhandle` in C include format.
+/// the size of a buffer holding `rows` rows of `columns` bytes.
+fn buffer_size(columns: usize, rows: usize) -> Result<usize, RxdError> {
+    columns
+        .checked_mul(rows)
+        .ok_or_else(|| RxdError::Usage(format!("{} columns is too many.", columns)))
+}
+
+/// prints bytes read from `inhandle` to `outhandle` in C include format, returning the
+/// number of bytes dumped.
 fn include_format(
-    inhandle: Box<dyn std::io::Read>,
+    inhandle: bufio::Input,
     outhandle: Box<dyn std::io::Write>,
     options: argparse::Options,
-) -> Result<(), RxdError> {
+) -> Result<u64, RxdError> {
     let columns = options.cols.unwrap_or(30);
-    let mut reader = bufio::LimitedBufReader::new(columns * 128, inhandle, options.len_octets);
+    let buffer_size = buffer_size(columns, 128)?;
+    let mut reader = bufio::LimitedBufReader::new(buffer_size, inhandle, options.len_octets);
 
     let mut buffer_name = options.include_name.clone().unwrap_or("buffer".into());
     let mut buffer_length_name = buffer_name.clone() + "_len";
@@ -79,7 +164,8 @@ fn include_format(
 
     outbuf.write_fmt(format_args!("unsigned char {}[] = {{\n", buffer_name))?;
 
-    while let Ok(n_bytes) = reader.read() {
+    loop {
+        let n_bytes = reader.read()?;
         if n_bytes == 0 {
             break;
         }
@@ -87,13 +173,13 @@ fn include_format(
         let buf = reader.as_ref();
 
         for row in buf.chunks(columns) {
-            outbuf.write("  ".as_bytes())?;
-            for byte in row.as_ref().into_iter() {
-                outbuf.write("0x".as_bytes())?;
-                to_lower_hex(&mut outbuf, &byte);
-                outbuf.write(", ".as_bytes())?;
+            outbuf.write_all("  ".as_bytes())?;
+            for byte in row.as_ref().iter() {
+                outbuf.write_all("0x".as_bytes())?;
+                to_lower_hex(&mut outbuf, byte)?;
+                outbuf.write_all(", ".as_bytes())?;
             }
-            outbuf.write("\n".as_bytes())?;
+            outbuf.write_all("\n".as_bytes())?;
         }
     }
 
@@ -103,21 +189,26 @@ fn include_format(
         reader.total_bytes_read()
     ))?;
 
-    Ok(())
+    // flush here rather than on drop, which would ignore a failed write
+    outbuf.flush()?;
+    Ok(reader.total_bytes_read())
 }
 
-/// prints bytes read from `inhandle` to `outhandle` in postscript (only hex bytes) format.
+/// prints bytes read from `inhandle` to `outhandle` in postscript (only hex bytes) format,
+/// returning the number of bytes dumped.
 fn postscript_format(
-    mut inhandle: Box<dyn std::io::Read>,
+    inhandle: bufio::Input,
     outhandle: Box<dyn std::io::Write>,
     options: argparse::Options,
-) -> Result<(), RxdError> {
+) -> Result<u64, RxdError> {
     let columns = options.cols.unwrap_or(16);
 
-    let mut reader = bufio::LimitedBufReader::new(columns * 128 * 16, inhandle, options.len_octets);
-    let mut writer = std::io::BufWriter::with_capacity(columns * 128 * 16, outhandle);
+    let buffer_size = buffer_size(columns, 128 * 16)?;
+    let mut reader = bufio::LimitedBufReader::new(buffer_size, inhandle, options.len_octets);
+    let mut writer = std::io::BufWriter::with_capacity(buffer_size, outhandle);
 
-    while let Ok(bytes_read) = reader.read() {
+    loop {
+        let bytes_read = reader.read()?;
         if bytes_read == 0 {
             break;
         }
@@ -125,29 +216,242 @@ fn postscript_format(
         let bytes = reader.as_ref();
         for chunk in bytes.chunks(columns) {
             for byte in chunk {
-                to_lower_hex(&mut writer, byte);
+                to_lower_hex(&mut writer, byte)?;
+            }
+            writer.write_all("\n".as_bytes())?; // don't need to check this result
+        }
+    }
+
+    writer.flush()?;
+    Ok(reader.total_bytes_read())
+}
+
+/// Writes a single byte into the hex column, like `format::to_lower_hex`.
+type ByteFormatter = fn(&mut dyn Write, &u8) -> std::io::Result<()>;
+
+/// How the rows of a regular format dump are laid out.
+struct RowLayout {
+    /// writes a single byte into the hex column
+    formatter: ByteFormatter,
+    /// the number of characters `formatter` writes per byte
+    cell_width: usize,
+    columns: usize,
+    group_size: usize,
+    is_little_endian: bool,
+    decimal_offset: bool,
+    /// the colors to use, or None to leave the output uncolored
+    theme: Option<theme::Theme>,
+}
+
+/// switches to the color of `byte`'s class, unless that's the class `current_class` is
+/// already showing. Does nothing when the output isn't colored.
+fn write_color(
+    buffer: &mut dyn Write,
+    layout: &RowLayout,
+    current_class: &mut Option<u8>,
+    byte: &u8,
+) -> Result<(), RxdError> {
+    if let Some(ref theme) = layout.theme {
+        let class = theme.class(*byte);
+        if *current_class != Some(class) {
+            buffer.write_all(theme.escape(class).as_bytes())?;
+            *current_class = Some(class);
+        }
+    }
+    Ok(())
+}
+
+/// prints a single row of a regular format dump, starting at `offset`.
+fn write_row(
+    buffer: &mut dyn Write,
+    layout: &RowLayout,
+    offset: u64,
+    row: &[u8],
+) -> Result<(), RxdError> {
+    let mut graphic_bytes = 0; // the amount of characters written to the hex column
+    let mut current_class: Option<u8> = None; // the byte class the terminal is colored for
+
+    if let Some(ref theme) = layout.theme {
+        buffer.write_all(Color::Bold.ansi().as_bytes())?;
+        buffer.write_all(theme.offset_escape().as_bytes())?;
+    }
+    if layout.decimal_offset {
+        buffer.write_fmt(format_args!("{:0>8}:", offset))?;
+    } else {
+        buffer.write_fmt(format_args!("{:0>8x}:", offset))?;
+    }
+    buffer.write_all(" ".as_bytes())?;
+
+    for group in row.chunks(layout.group_size) {
+        if layout.is_little_endian {
+            for byte in group.iter().rev() {
+                write_color(buffer, layout, &mut current_class, byte)?;
+                (layout.formatter)(buffer, byte)?;
+                graphic_bytes += layout.cell_width;
+            }
+        } else {
+            for byte in group.iter() {
+                write_color(buffer, layout, &mut current_class, byte)?;
+                (layout.formatter)(buffer, byte)?;
+                graphic_bytes += layout.cell_width;
             }
-            writer.write("\n".as_bytes())?; // don't need to check this result
+        }
+
+        buffer.write_all(" ".as_bytes())?;
+        graphic_bytes += 1;
+    }
+
+    // padding calculation; check how many characters the hex column needs to be padded
+    // out by so that the ASCII column appears in a straight line.
+    let total_width =
+        layout.columns * layout.cell_width + layout.columns.div_ceil(layout.group_size);
+    let padding = total_width.saturating_sub(graphic_bytes);
+
+    for _ in 0..padding {
+        buffer.write_all(" ".as_bytes())?;
+    }
+
+    buffer.write_all(" ".as_bytes())?;
+
+    for byte in row {
+        write_color(buffer, layout, &mut current_class, byte)?;
+
+        if !byte.is_ascii_graphic() && *byte != 0x20 {
+            buffer.write_all(".".as_bytes())?;
+        } else {
+            buffer.write_all(&[*byte])?;
         }
     }
 
+    if layout.theme.is_some() {
+        buffer.write_all(Color::Reset.ansi().as_bytes())?;
+    }
+
+    buffer.write_all("\n".as_bytes())?;
+
     Ok(())
 }
 
-/// prints bytes read from `inhandle` to `outhandle` in xxd's regular format.
+/// prints the rows of a regular format dump one after another, working out their offsets
+/// and squeezing repeated rows for autoskip and squeeze.
+struct RowPrinter {
+    layout: RowLayout,
+    autoskip: bool,
+    squeeze: bool,
+    /// the offset of the first row, before `display_offset` is added
+    start_offset: u64,
+    /// added to every offset shown, from -o
+    display_offset: i64,
+    row_counter: u64,
+    // autoskip/squeeze state: the previous row, and the rows held back since the last
+    // printed row.
+    previous_row: Vec<u8>,
+    skipped_rows: usize,
+    last_skipped_row: Vec<u8>,
+    last_skipped_offset: u64,
+}
+
+impl RowPrinter {
+    fn new(layout: RowLayout, options: &argparse::Options, start_offset: u64) -> Self {
+        let columns = layout.columns;
+        RowPrinter {
+            layout,
+            autoskip: options.autoskip,
+            squeeze: options.squeeze,
+            start_offset,
+            display_offset: options.offset,
+            row_counter: 0,
+            previous_row: Vec::with_capacity(columns),
+            skipped_rows: 0,
+            last_skipped_row: Vec::with_capacity(columns),
+            last_skipped_offset: 0,
+        }
+    }
+
+    /// prints the next row, or holds it back if it repeats the one before.
+    fn print(&mut self, buffer: &mut dyn Write, row: &[u8]) -> Result<(), RxdError> {
+        let offset = (self.start_offset + self.row_counter * self.layout.columns as u64)
+            .wrapping_add_signed(self.display_offset);
+        self.row_counter += 1;
+
+        let is_repeat = row == self.previous_row.as_slice()
+            && (self.squeeze || self.autoskip && row.iter().all(|byte| *byte == 0));
+        self.previous_row.clear();
+        self.previous_row.extend_from_slice(row);
+
+        if is_repeat {
+            self.skipped_rows += 1;
+            self.last_skipped_row.clear();
+            self.last_skipped_row.extend_from_slice(row);
+            self.last_skipped_offset = offset;
+            return Ok(());
+        }
+
+        // a single held back row takes up as much space as the '*' would
+        if self.skipped_rows == 1 {
+            write_row(
+                buffer,
+                &self.layout,
+                self.last_skipped_offset,
+                &self.last_skipped_row,
+            )?;
+        } else if self.skipped_rows > 1 {
+            buffer.write_all("*\n".as_bytes())?;
+        }
+        self.skipped_rows = 0;
+
+        write_row(buffer, &self.layout, offset, row)
+    }
+
+    /// prints whatever is still held back. The last row is always printed, so that the
+    /// length of the dump is visible.
+    fn finish(&mut self, buffer: &mut dyn Write) -> Result<(), RxdError> {
+        if self.skipped_rows > 1 {
+            buffer.write_all("*\n".as_bytes())?;
+        }
+        if self.skipped_rows > 0 {
+            write_row(
+                buffer,
+                &self.layout,
+                self.last_skipped_offset,
+                &self.last_skipped_row,
+            )?;
+        }
+        self.skipped_rows = 0;
+        Ok(())
+    }
+}
+
+/// prints bytes read from `inhandle` to `outhandle` in xxd's regular format, returning the
+/// number of bytes dumped.
+///
+/// The offset column shows `start_offset` (where `inhandle` was seeked to) plus the
+/// position in `inhandle`, plus `options.offset`.
+///
+/// With `options.autoskip`, a run of all-zero rows is printed as its first row followed by
+/// a single '*'. `options.squeeze` does the same for runs of any identical rows, like
+/// `hexdump -C`. The row after the run is printed with its real offset, and the last row
+/// of the dump is always printed.
 fn regular_format(
-    mut inhandle: Box<dyn std::io::Read>,
+    inhandle: bufio::Input,
     outhandle: Box<dyn std::io::Write>,
     options: argparse::Options,
-    is_terminal: bool,
-) -> Result<(), RxdError> {
+    start_offset: u64,
+    theme: Option<theme::Theme>,
+) -> Result<u64, RxdError> {
     // Doing this as branching might be a problem (if dispatch isn't...) and it's easier to manage the code here
-    let formatter = if options.bits {
-        to_binary
-    } else if options.uppercase {
-        to_upper_hex
-    } else {
-        to_lower_hex
+    // cell_width is the number of characters the formatter writes per byte
+    let (formatter, cell_width): (ByteFormatter, usize) = match (
+        options.bits,
+        options.bits_lsb_first,
+        options.nibble_separator,
+    ) {
+        (true, false, false) => (to_binary, 8),
+        (true, true, false) => (to_binary_lsb, 8),
+        (true, false, true) => (to_binary_nibbles, 9),
+        (true, true, true) => (to_binary_lsb_nibbles, 9),
+        (false, _, _) if options.uppercase => (to_upper_hex, 2),
+        (false, _, _) => (to_lower_hex, 2),
     };
 
     let columns = options.cols.unwrap_or(if options.include_format {
@@ -158,149 +462,269 @@ fn regular_format(
         16
     });
 
-    let mut row_counter: usize = 0;
+    let layout = RowLayout {
+        formatter,
+        cell_width,
+        columns,
+        group_size: options.group_size(),
+        is_little_endian: options.is_little_endian,
+        decimal_offset: options.decimal_offset,
+        theme,
+    };
 
-    let mut reader = bufio::LimitedBufReader::new(columns * 128 * 16, inhandle, options.len_octets);
+    let buffer_size = buffer_size(columns, 128 * 16)?;
+    let mut reader = bufio::LimitedBufReader::new(buffer_size, inhandle, options.len_octets);
     let mut buffer = std::io::BufWriter::with_capacity(8192, outhandle);
-
-    while let Ok(bytes_read) = reader.read() {
+    let mut rows = RowPrinter::new(layout, &options, start_offset);
+
+    // the start of a row that didn't fit in the last read, finished by the next one. This
+    // keeps rows aligned to `columns` however the input is split up.
+    let mut partial_row: Vec<u8> = Vec::with_capacity(columns);
+
+    loop {
+        let bytes_read = match reader.read() {
+            Ok(bytes_read) => bytes_read,
+            Err(e) => {
+                // show everything that was read before the error
+                if !partial_row.is_empty() {
+                    rows.print(&mut buffer, &partial_row)?;
+                }
+                rows.finish(&mut buffer)?;
+                return Err(e);
+            }
+        };
         if bytes_read == 0 {
             break;
         }
 
-        let bytes = reader.as_ref();
+        let mut bytes = reader.as_ref();
 
-        for slice in bytes.get(0..bytes_read).unwrap().chunks(columns) {
-            let mut graphic_bytes = 0; // the amount of graphic bytes written to line_hexbuf
-
-            fn get_colour(byte: &u8) -> Color {
-                if *byte == 0 {
-                    Color::White
-                } else if *byte == 0xa || *byte == 0x9 || *byte == 0x20 {
-                    Color::Yellow
-                } else if *byte == 0xff {
-                    Color::Blue
-                } else if byte.is_ascii_graphic() {
-                    Color::Green
-                } else {
-                    Color::Red
-                }
-            }
-
-            buffer.write_fmt(format_args!("{:0>8x}: ", row_counter * columns))?;
-            if is_terminal {
-                buffer.write(Color::Bold.ansi().as_bytes())?;
+        if !partial_row.is_empty() {
+            let needed = (columns - partial_row.len()).min(bytes.len());
+            partial_row.extend_from_slice(&bytes[..needed]);
+            bytes = &bytes[needed..];
+            if partial_row.len() < columns {
+                continue;
             }
+            rows.print(&mut buffer, &partial_row)?;
+            partial_row.clear();
+        }
 
-            for group in slice.chunks(options.group_size) {
-                if options.is_little_endian {
-                    for byte in group.iter().rev() {
-                        if is_terminal {
-                            let colour = get_colour(byte);
-                            buffer.write(colour.ansi().as_bytes())?;
-                        }
-                        formatter(&mut buffer, &byte);
-                        graphic_bytes += 2;
-                    }
-                } else {
-                    for byte in group.iter() {
-                        if is_terminal {
-                            let colour = get_colour(byte);
-                            buffer.write(colour.ansi().as_bytes())?;
-                        }
-                        formatter(&mut buffer, &byte);
-                        graphic_bytes += 2;
-                    }
-                }
+        let whole_rows = bytes.len() - bytes.len() % columns;
+        for slice in bytes[..whole_rows].chunks(columns) {
+            rows.print(&mut buffer, slice)?;
+        }
+        partial_row.extend_from_slice(&bytes[whole_rows..]);
+    }
 
-                buffer.write(" ".as_bytes())?;
-            }
+    if !partial_row.is_empty() {
+        rows.print(&mut buffer, &partial_row)?;
+    }
+    rows.finish(&mut buffer)?;
 
-            buffer.write(" ".as_bytes())?;
+    buffer.flush()?;
+    Ok(reader.total_bytes_read())
+}
 
-            for group in slice.chunks(options.group_size) {
-                for byte in group {
-                    if is_terminal {
-                        let colour = get_colour(byte);
-                        buffer.write(colour.ansi().as_bytes())?;
-                    }
+/// opens `infile` (or stdin, when it's None) and seeks to where `options.seek` says the dump
+/// starts. Returns the input along with that position, which is shown in the offset column.
+fn open_input(
+    infile: Option<&str>,
+    options: &argparse::Options,
+) -> Result<(bufio::Input, u64), RxdError> {
+    let mut start_offset: u64 = 0;
+    let seek_error = |e: std::io::Error| {
+        RxdError::SeekError(format!("Could not seek to {}: {}", options.seek, e))
+    };
 
-                    if !byte.is_ascii_graphic() && *byte != 0x20 {
-                        buffer.write(".".as_bytes())?;
-                    } else {
-                        buffer.write(&[*byte])?;
-                    }
+    let inhandle = match infile {
+        Some(filename) => match fs::File::open(filename) {
+            Err(err) => {
+                return Err(RxdError::InputError(format!(
+                    "Could not open {}: {}",
+                    filename, err
+                )));
+            }
+            Ok(mut handle) => {
+                // when reverting, -s displaces the output instead of seeking into the input
+                if options.seek > 0 && options.seek_relative && !options.revert {
+                    start_offset = handle
+                        .seek(SeekFrom::Current(options.seek))
+                        .map_err(seek_error)?;
+                } else if options.seek > 0 && !options.revert {
+                    start_offset = handle
+                        .seek(SeekFrom::Start(options.seek.abs_diff(0)))
+                        .map_err(seek_error)?;
+                } else if options.seek < 0 && !options.revert {
+                    start_offset = handle
+                        .seek(SeekFrom::End(options.seek))
+                        .map_err(seek_error)?;
+                }
+                if options.mmap && !options.revert {
+                    // SAFETY: the file must not change while it is mapped. rxd itself never
+                    // writes to a file it dumps (the output is truncated before the input is
+                    // opened, so `rxd f f` leaves nothing to map), but other processes can:
+                    // if the file is truncated, reading the part that's gone kills rxd with
+                    // SIGBUS, and other writes show up in the dump halfway through. That's
+                    // why mapping is only done when asked for with -mmap, which documents it.
+                    unsafe { bufio::Input::from_file(handle, start_offset) }
+                } else {
+                    // never map hex dumps: they are read line by line, so mapping them gains
+                    // nothing, and `rxd -r f f` would patch the file it has mapped
+                    bufio::Input::Stream(Box::new(handle))
                 }
             }
-
-            // padding calculation; check how many bytes line_hexbuf needs to be padded out
-            // so that line_buf appears in a straight line.
-            let total_width = columns * 2 + (columns / options.group_size);
-            let padding = if total_width < graphic_bytes {
-                0
+        },
+        None => {
+            // stdin is usually a pipe, so seek by reading: skip bytes to seek forwards, and
+            // keep the tail of the input to seek backwards from its end
+            let mut handle = std::io::stdin();
+            if options.seek > 0 && !options.revert {
+                start_offset =
+                    bufio::skip_bytes(&mut handle, options.seek.abs_diff(0)).map_err(seek_error)?;
+                bufio::Input::Stream(Box::new(handle))
+            } else if options.seek < 0 && !options.revert {
+                let (tail, tail_offset) =
+                    bufio::tail_bytes(&mut handle, options.seek.abs_diff(0)).map_err(seek_error)?;
+                start_offset = tail_offset;
+                bufio::Input::Stream(Box::new(std::io::Cursor::new(tail)))
             } else {
-                total_width - graphic_bytes
-            };
-
-            for _ in 0..padding {
-                buffer.write(" ".as_bytes())?;
+                bufio::Input::Stream(Box::new(handle))
             }
+        }
+    };
+
+    Ok((inhandle, start_offset))
+}
+
+/// dumps `inhandle` to `outhandle` in the format `options` asks for, returning the number
+/// of bytes dumped.
+fn dump(
+    inhandle: bufio::Input,
+    outhandle: Box<dyn std::io::Write>,
+    options: argparse::Options,
+    start_offset: u64,
+    theme: Option<theme::Theme>,
+) -> Result<u64, RxdError> {
+    let result = if options.include_format {
+        include_format(inhandle, outhandle, options)
+    } else if options.postscript_style {
+        postscript_format(inhandle, outhandle, options)
+    } else {
+        regular_format(inhandle, outhandle, options, start_offset, theme)
+    };
 
-            buffer.write(Color::Reset.ansi().as_bytes())?;
+    // the reader counts from where the dump started, but the error should name the offset
+    // in the input
+    result.map_err(|e| match e {
+        RxdError::ReadError { offset, error } => RxdError::ReadError {
+            offset: start_offset + offset,
+            error,
+        },
+        e => e,
+    })
+}
 
-            buffer.write("\n".as_bytes())?;
+/// dumps each of `options.infiles` to stdout after a `==> name <==` header, like head(1)
+/// does for multiple files. Files that can't be opened are reported and skipped, and
+/// counted in the error returned at the end.
+///
+/// With `options.continue_offsets`, each file's offsets start where the previous file's
+/// dump ended, so the files read as one stream.
+fn dump_files(options: &argparse::Options, theme: Option<theme::Theme>) -> Result<(), RxdError> {
+    let mut next_offset: Option<u64> = None;
+    let mut failed_files = 0;
+
+    for filename in options.infiles.iter() {
+        let (inhandle, start_offset) = match open_input(Some(filename), options) {
+            Ok(input) => input,
+            Err(e @ RxdError::InputError(_)) => {
+                eprintln!("{}", e);
+                failed_files += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
 
-            row_counter += 1;
+        let mut stdout = std::io::stdout();
+        if next_offset.is_some() {
+            stdout.write_all("\n".as_bytes())?;
         }
+        stdout.write_fmt(format_args!("==> {} <==\n", filename))?;
+
+        let start_offset = match next_offset {
+            Some(offset) if options.continue_offsets => offset,
+            _ => start_offset,
+        };
+        let bytes_dumped = dump(
+            inhandle,
+            Box::new(stdout),
+            options.clone(),
+            start_offset,
+            theme.clone(),
+        )?;
+        next_offset = Some(start_offset + bytes_dumped);
+    }
+
+    if failed_files > 0 {
+        return Err(RxdError::InputError(format!(
+            "Could not open {} of {} files.",
+            failed_files,
+            options.infiles.len()
+        )));
     }
 
     Ok(())
 }
 
-fn main() {
+fn run() -> Result<(), RxdError> {
     use argparse::Options;
 
-    let arguments: Vec<String> = env::args().collect();
-    let options = match Options::parse_options(arguments[1..].to_owned()) {
-        Ok(opt) => opt,
-        Err(err) => {
-            println!("{}", err);
-            println!("{}", HELP_TEXT);
-            return;
-        }
-    };
+    // file names are kept as Strings, so arguments that aren't UTF-8 can't be used
+    let arguments = env::args_os()
+        .skip(1)
+        .map(|argument| {
+            argument.into_string().map_err(|argument| {
+                RxdError::Usage(format!(
+                    "{} is not valid UTF-8.",
+                    argument.to_string_lossy()
+                ))
+            })
+        })
+        .collect::<Result<Vec<String>, RxdError>>()?;
+    let options = Options::parse_options(arguments).map_err(RxdError::Usage)?;
 
     if options.display_help {
-        println!("{}", HELP_TEXT);
-        return;
+        writeln!(std::io::stdout(), "{}", HELP_TEXT)?;
+        return Ok(());
     }
 
     if options.display_version {
-        println!("{}", VERSION);
-        return;
+        writeln!(std::io::stdout(), "{}", VERSION)?;
+        return Ok(());
     }
 
-    let inhandle: Box<dyn std::io::Read> = match options.infile {
-        Some(ref filename) => match fs::File::open(&filename) {
-            Err(err) => {
-                println!("Could not open {}: {}", &filename, err.to_string());
-                return;
-            }
-            Ok(mut handle) => {
-                if options.seek > 0 {
-                    handle
-                        .seek(SeekFrom::Start(options.seek.abs_diff(0).into()))
-                        .expect("Could not seek to location.");
-                } else if options.seek < 0 {
-                    handle
-                        .seek(SeekFrom::End(options.seek.into()))
-                        .expect("Could not seek to location.");
-                }
-                Box::new(handle)
-            }
-        },
-        None => Box::new(std::io::stdin()),
-    };
+    if options.revert {
+        let (inhandle, _) = open_input(options.infile.as_deref(), &options)?;
+
+        // patch the output file in place rather than truncating it
+        let target = match options.outfile {
+            None => revert::RevertTarget::Stream(Box::new(std::io::stdout())),
+            Some(ref filename) => revert::RevertTarget::File(
+                fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(filename)
+                    .map_err(|e| {
+                        RxdError::OutputError(format!("Could not open {}: {}", filename, e))
+                    })?,
+            ),
+        };
+
+        return revert::revert(Box::new(inhandle), target, options);
+    }
 
     let (outhandle, is_terminal): (Box<dyn std::io::Write>, bool) = match options.outfile {
         None => {
@@ -309,30 +733,137 @@ fn main() {
             (Box::new(stdout), is_terminal)
         }
         Some(ref filename) => {
-            let file = fs::File::create(filename).expect("Could not create output file.");
+            let file = fs::File::create(filename).map_err(|e| {
+                RxdError::OutputError(format!("Could not create {}: {}", filename, e))
+            })?;
             let is_terminal = file.is_terminal();
 
             (Box::new(file), is_terminal)
         }
     };
 
-    if options.revert {
-        if let Err(e) = revert::revert(inhandle, outhandle, options) {
-            println!("{:?}", e);
+    // -R always and -R never override the environment; NO_COLOR wins over CLICOLOR_FORCE
+    let is_set = |name: &str| env::var_os(name).is_some_and(|value| !value.is_empty());
+    let colorize = match options.color {
+        argparse::ColorMode::Always => true,
+        argparse::ColorMode::Never => false,
+        argparse::ColorMode::Auto if is_set("NO_COLOR") => false,
+        argparse::ColorMode::Auto if is_set("CLICOLOR_FORCE") => {
+            env::var_os("CLICOLOR_FORCE").is_some_and(|value| value != "0")
+        }
+        argparse::ColorMode::Auto => is_terminal,
+    };
+
+    let theme = if colorize {
+        Some(theme::Theme::load()?)
+    } else {
+        None
+    };
+
+    if options.multiple_files && !options.infiles.is_empty() {
+        return dump_files(&options, theme);
+    }
+
+    let (inhandle, start_offset) = open_input(options.infile.as_deref(), &options)?;
+    dump(inhandle, outhandle, options, start_offset, theme)?;
+
+    Ok(())
+}
+
+fn main() {
+    match run() {
+        Ok(()) => {}
+        // whoever was reading the output has stopped, like `head` does once it has enough,
+        // so there's no one left to tell
+        Err(RxdError::IoError(e)) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            // unlike eprintln!, don't panic if stderr is closed; the exit status still says
+            // what went wrong
+            let mut stderr = std::io::stderr();
+            let _ = writeln!(stderr, "{}", e);
+            if let RxdError::Usage(_) = e {
+                let _ = writeln!(stderr, "{}", HELP_TEXT);
+            }
+            std::process::exit(e.exit_code());
         }
-    } else if options.include_format {
-        if let Err(e) = include_format(inhandle, outhandle, options) {
-            println!("{:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// collects what's written to it where the test can still get at it after it was
+    /// handed over as a `Box<dyn Write>`.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
         }
-    } else if options.postscript_style {
-        if let Err(e) = postscript_format(inhandle, outhandle, options) {
-            println!("{:?}", e);
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
         }
-        return;
-    } else {
-        if let Err(e) = regular_format(inhandle, outhandle, options, is_terminal) {
-            println!("{:?}", e);
+    }
+
+    fn regular_dump(input: bufio::Input, options: argparse::Options) -> String {
+        let output = SharedBuffer::default();
+        regular_format(input, Box::new(output.clone()), options, 0, None).unwrap();
+        let text = output.0.borrow().clone();
+        String::from_utf8(text).unwrap()
+    }
+
+    /// 100000 bytes with runs of NULs and of a repeated byte across the end of the first
+    /// 32768 byte read.
+    fn data() -> Vec<u8> {
+        (0..100_000u32)
+            .map(|i| match i {
+                30_000..40_000 => 0,
+                60_000..70_000 => 0xab,
+                _ => (i * 7 % 251) as u8,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rows_do_not_depend_on_how_reads_are_split() {
+        let data = data();
+        let variants = [
+            argparse::Options::default(),
+            argparse::Options {
+                autoskip: true,
+                ..Default::default()
+            },
+            argparse::Options {
+                squeeze: true,
+                ..Default::default()
+            },
+            argparse::Options {
+                squeeze: true,
+                cols: Some(7),
+                len_octets: Some(65_001),
+                ..Default::default()
+            },
+        ];
+
+        for options in variants {
+            let whole = regular_dump(
+                bufio::Input::Stream(Box::new(std::io::Cursor::new(data.clone()))),
+                options.clone(),
+            );
+            let trickled = regular_dump(
+                bufio::Input::Stream(Box::new(bufio::tests::Trickle::new(&data))),
+                options.clone(),
+            );
+            assert_eq!(trickled, whole, "with {options:?}");
+            if options.autoskip || options.squeeze {
+                assert!(whole.contains("\n*\n"), "nothing skipped with {options:?}");
+            }
         }
-        return;
     }
 }
@@ -0,0 +1,109 @@
+use std::fs;
+use std::io;
+
+/// A read-only, private memory mapping of a whole file.
+///
+/// The mapping shows the file as it is on disk, so the bytes behind it change if the file
+/// is written to while it is mapped, and go away (raising SIGBUS) if it is truncated. See
+/// `Mapping::map` for what callers have to promise.
+pub struct Mapping {
+    ptr: *const u8,
+    len: usize,
+}
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod sys {
+    use std::ffi::{c_int, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+
+    unsafe extern "C" {
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: i64,
+        ) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+impl Mapping {
+    /// Maps all of `file` into memory. Fails for empty files and anything that isn't a
+    /// regular file, as well as on platforms without mmap; callers should fall back to
+    /// reading the file instead.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be written to or truncated, by this process or any other, for as
+    /// long as the mapping lives. The slice `as_ref` returns would change underneath its
+    /// borrowers otherwise, which is undefined behavior.
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    pub unsafe fn map(file: &fs::File) -> io::Result<Mapping> {
+        use std::os::fd::AsRawFd;
+
+        let metadata = file.metadata()?;
+        if !metadata.is_file() || metadata.len() == 0 {
+            return Err(io::Error::from(io::ErrorKind::Unsupported));
+        }
+        let len = usize::try_from(metadata.len())
+            .map_err(|_| io::Error::from(io::ErrorKind::FileTooLarge))?;
+
+        // SAFETY: we ask for a fresh read-only mapping of a file we hold open, and check for
+        // MAP_FAILED before using it. Keeping the file unchanged is up to our caller.
+        let ptr = unsafe {
+            sys::mmap(
+                std::ptr::null_mut(),
+                len,
+                sys::PROT_READ,
+                sys::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Mapping {
+            ptr: ptr as *const u8,
+            len,
+        })
+    }
+
+    /// # Safety
+    ///
+    /// See the unix version; this one never maps anything.
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    pub unsafe fn map(_file: &fs::File) -> io::Result<Mapping> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl AsRef<[u8]> for Mapping {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` readable bytes that live until we unmap them in drop.
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        // SAFETY: `ptr` and `len` came from a successful mmap and haven't been unmapped.
+        unsafe {
+            sys::munmap(self.ptr as *mut std::ffi::c_void, self.len);
+        }
+    }
+}
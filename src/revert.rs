@@ -1,18 +1,1028 @@
+use crate::RxdError;
 use crate::argparse::Options;
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
 
+/// Returns the value of a hex digit, or None if `c` isn't one.
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Where reverted bytes end up.
+pub enum RevertTarget {
+    /// A stream that can only be written to sequentially, like stdout.
+    Stream(Box<dyn Write>),
+    /// A file that is patched in place: only the offsets found in the hex dump are
+    /// overwritten, and everything else in the file is left as it was.
+    File(std::fs::File),
+}
+
+enum RevertOutput {
+    Stream(std::io::BufWriter<Box<dyn Write>>),
+    File(std::io::BufWriter<std::fs::File>),
+}
+
+/// Returns the value of a digit of a line's offset, which is decimal with -d and hex otherwise.
+fn offset_digit_value(c: u8, decimal_offsets: bool) -> Option<u8> {
+    match c {
+        b'0'..=b'9' if decimal_offsets => Some(c - b'0'),
+        _ if decimal_offsets => None,
+        _ => hex_value(c),
+    }
+}
+
+/// Keeps track of where in the output we're writing, so that each line of the hex dump
+/// ends up at the offset it names. Streams get the gaps between lines filled with zeros,
+/// files get seeked (and are extended with zeros when seeking past their end).
+struct RevertWriter {
+    output: RevertOutput,
+    /// the offset the next write to `output` will land at
+    position: u64,
+    /// added to every offset passed to `write_at`; comes from -s
+    displacement: i64,
+    /// writes held back until `finish`, so that nothing is written if the dump turns out to
+    /// be malformed halfway through; only used in strict mode
+    held: Option<Vec<(u64, Vec<u8>)>>,
+}
+
+impl RevertWriter {
+    /// Creates a writer for `target`. With `hold`, every write is kept in memory until
+    /// `finish` is called instead of going straight to the output.
+    fn new(target: RevertTarget, displacement: i64, hold: bool) -> Self {
+        let output = match target {
+            RevertTarget::Stream(handle) => {
+                RevertOutput::Stream(std::io::BufWriter::with_capacity(8192, handle))
+            }
+            RevertTarget::File(handle) => {
+                RevertOutput::File(std::io::BufWriter::with_capacity(8192, handle))
+            }
+        };
+
+        RevertWriter {
+            output,
+            position: 0,
+            displacement,
+            held: hold.then(Vec::new),
+        }
+    }
+
+    /// Writes `bytes` at `offset` (plus the displacement).
+    fn write_at(&mut self, offset: u64, bytes: &[u8]) -> Result<(), RxdError> {
+        if let Some(ref mut held) = self.held {
+            held.push((offset, bytes.to_vec()));
+            return Ok(());
+        }
+
+        let offset = offset
+            .checked_add_signed(self.displacement)
+            .ok_or_else(|| {
+                RxdError::SeekError("Sorry, cannot seek before the start of the output.".to_owned())
+            })?;
+        let end = offset.checked_add(bytes.len() as u64).ok_or_else(|| {
+            RxdError::SeekError("Sorry, cannot seek past the end of the output.".to_owned())
+        })?;
+
+        match self.output {
+            RevertOutput::Stream(ref mut handle) => {
+                if offset < self.position {
+                    return Err(RxdError::SeekError(
+                        "Sorry, cannot seek backwards.".to_owned(),
+                    ));
+                }
+
+                const ZEROES: [u8; 4096] = [0; 4096];
+                while self.position < offset {
+                    let gap = std::cmp::min(offset - self.position, ZEROES.len() as u64);
+                    handle.write_all(&ZEROES[..gap as usize])?;
+                    self.position += gap;
+                }
+
+                handle.write_all(bytes)?;
+            }
+            RevertOutput::File(ref mut handle) => {
+                if offset != self.position {
+                    handle.seek(SeekFrom::Start(offset))?;
+                }
+
+                handle.write_all(bytes)?;
+            }
+        }
+
+        self.position = end;
+        Ok(())
+    }
+
+    /// Writes out anything that was held back and flushes the output.
+    fn finish(mut self) -> Result<(), RxdError> {
+        if let Some(held) = self.held.take() {
+            for (offset, bytes) in held {
+                self.write_at(offset, &bytes)?;
+            }
+        }
+
+        match self.output {
+            RevertOutput::Stream(mut handle) => handle.flush()?,
+            RevertOutput::File(mut handle) => handle.flush()?,
+        }
+        Ok(())
+    }
+}
+
+/// How each byte is spelled out in the hex column of a regular dump.
+#[derive(Clone, Copy)]
+enum ByteEncoding {
+    /// two hex digits per byte
+    Hex,
+    /// eight binary digits per byte, optionally split into nibbles by a '_' (-b)
+    Bits { lsb_first: bool },
+}
+
+impl ByteEncoding {
+    fn digit_value(&self, c: u8) -> Option<u8> {
+        match self {
+            ByteEncoding::Hex => hex_value(c),
+            ByteEncoding::Bits { .. } => match c {
+                b'0' | b'1' => Some(c - b'0'),
+                _ => None,
+            },
+        }
+    }
+
+    fn radix(&self) -> u8 {
+        match self {
+            ByteEncoding::Hex => 16,
+            ByteEncoding::Bits { .. } => 2,
+        }
+    }
+
+    fn digits_per_byte(&self) -> usize {
+        match self {
+            ByteEncoding::Hex => 2,
+            ByteEncoding::Bits { .. } => 8,
+        }
+    }
+
+    /// Whether `c` can sit between the two nibbles of a byte.
+    fn is_nibble_separator(&self, c: u8) -> bool {
+        matches!(self, ByteEncoding::Bits { .. }) && c == b'_'
+    }
+
+    /// Turns the digits read for a byte, taken most significant first, into the byte.
+    fn finish_byte(&self, digits: u8) -> u8 {
+        match self {
+            ByteEncoding::Bits { lsb_first: true } => digits.reverse_bits(),
+            _ => digits,
+        }
+    }
+}
+
+/// Parses a single line of a regular dump, returning the offset the line starts at
+/// and the bytes it contains. Returns None if the line doesn't have an offset, or if the
+/// offset doesn't fit in 64 bits.
+///
+/// Anything before the first digit is ignored, and the offset runs up to the first
+/// character that isn't a digit (usually the ':'). The offset is decimal if
+/// `decimal_offsets` is set and hex otherwise. After that, runs of digits are read
+/// as bytes according to `encoding` until `columns` bytes have been read, or until three
+/// characters in a row fail to contribute to a byte, which is how the ASCII column gets
+/// skipped over. A byte with too few digits before the next non-digit is dropped.
+fn parse_line(
+    line: &[u8],
+    columns: usize,
+    encoding: ByteEncoding,
+    decimal_offsets: bool,
+) -> Option<(u64, Vec<u8>)> {
+    let start = line
+        .iter()
+        .position(|c| offset_digit_value(*c, decimal_offsets).is_some())?;
+    let radix = if decimal_offsets { 10 } else { 16 };
+
+    let mut offset: u64 = 0;
+    let mut cursor = start;
+    loop {
+        match line.get(cursor) {
+            None => return None,
+            Some(c) => match offset_digit_value(*c, decimal_offsets) {
+                Some(digit) => {
+                    offset = offset.checked_mul(radix)?.checked_add(digit as u64)?;
+                }
+                None => break,
+            },
+        }
+        cursor += 1;
+    }
+
+    let mut bytes = Vec::with_capacity(columns);
+    let mut partial_byte: u8 = 0;
+    let mut digits_read = 0;
+    // the offset terminator counts as the first character that didn't contribute to a byte
+    let mut garbage_run = 1;
+
+    for c in line.iter().skip(cursor + 1) {
+        if *c == b'\r' || encoding.is_nibble_separator(*c) {
+            continue;
+        }
+
+        match encoding.digit_value(*c) {
+            Some(digit) => {
+                partial_byte = partial_byte.wrapping_mul(encoding.radix()) | digit;
+                digits_read += 1;
+                garbage_run = 0;
+
+                if digits_read == encoding.digits_per_byte() {
+                    bytes.push(encoding.finish_byte(partial_byte));
+                    partial_byte = 0;
+                    digits_read = 0;
+                    garbage_run = 1;
+                    if bytes.len() >= columns {
+                        break;
+                    }
+                }
+            }
+            None => {
+                partial_byte = 0;
+                digits_read = 0;
+                garbage_run += 1;
+                if garbage_run >= 3 {
+                    break;
+                }
+            }
+        }
+    }
+
+    Some((offset, bytes))
+}
+
+/// Builds the error reported for a malformed hex dump in strict mode.
+/// `line` and `column` are 1-based, `column` counts bytes.
+fn parse_error(line: usize, column: usize, text: &[u8], reason: &str) -> RxdError {
+    RxdError::ParseError {
+        line,
+        column,
+        text: String::from_utf8_lossy(text).into_owned(),
+        reason: reason.to_owned(),
+    }
+}
+
+/// Parses a single line of a regular dump like `parse_line`, but fails on anything that
+/// doesn't look exactly like `regular_format`'s output instead of skipping over it.
+///
+/// The line must be an offset (in decimal if `decimal_offsets` is set) followed by a ':',
+/// then groups of digits separated by single spaces. The groups end at the first double
+/// space (where the ASCII column starts) or the end of the line. Blank lines are allowed and
+/// return None. The lines that are just a '*' never get here, `revert_regular` handles them.
+fn parse_line_strict(
+    line: &[u8],
+    line_number: usize,
+    columns: usize,
+    encoding: ByteEncoding,
+    decimal_offsets: bool,
+) -> Result<Option<(u64, Vec<u8>)>, RxdError> {
+    let line = line.trim_ascii_end();
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    let colon = match line.iter().position(|c| *c == b':') {
+        Some(colon) => colon,
+        None => {
+            return Err(parse_error(
+                line_number,
+                1,
+                line,
+                "missing ':' after the offset",
+            ));
+        }
+    };
+    let offset_text = &line[..colon];
+    if offset_text.is_empty() {
+        return Err(parse_error(line_number, 1, offset_text, "missing offset"));
+    }
+    if let Some(bad) = offset_text
+        .iter()
+        .position(|c| offset_digit_value(*c, decimal_offsets).is_none())
+    {
+        return Err(parse_error(
+            line_number,
+            bad + 1,
+            offset_text,
+            "malformed offset",
+        ));
+    }
+    let offset = offset_text
+        .iter()
+        .try_fold(0u64, |offset, c| {
+            let radix = if decimal_offsets { 10 } else { 16 };
+            let digit = offset_digit_value(*c, decimal_offsets)?;
+            offset.checked_mul(radix)?.checked_add(digit as u64)
+        })
+        .ok_or_else(|| parse_error(line_number, 1, offset_text, "offset is too large"))?;
+
+    // the hex column runs up to the double space in front of the ASCII column
+    let mut hex_column = &line[colon + 1..];
+    let hex_start = colon + 1;
+    if let Some(end) = hex_column.windows(2).position(|pair| pair == b"  ") {
+        hex_column = &hex_column[..end];
+    }
+
+    let mut bytes = Vec::with_capacity(columns);
+    let mut group_start = 0;
+    for group in hex_column.split(|c| *c == b' ') {
+        let column = hex_start + group_start + 1;
+        group_start += group.len() + 1;
+
+        // the space after the ':' leaves an empty group at the start
+        if group.is_empty() && column == hex_start + 1 {
+            continue;
+        }
+
+        let mut partial_byte: u8 = 0;
+        let mut digits_read = 0;
+        for (i, c) in group.iter().enumerate() {
+            if encoding.is_nibble_separator(*c) && digits_read == encoding.digits_per_byte() / 2 {
+                continue;
+            }
+
+            match encoding.digit_value(*c) {
+                Some(digit) => {
+                    partial_byte = partial_byte.wrapping_mul(encoding.radix()) | digit;
+                    digits_read += 1;
+                    if digits_read == encoding.digits_per_byte() {
+                        bytes.push(encoding.finish_byte(partial_byte));
+                        partial_byte = 0;
+                        digits_read = 0;
+                    }
+                }
+                None => {
+                    let reason = match encoding {
+                        ByteEncoding::Hex => "not a hex digit",
+                        ByteEncoding::Bits { .. } => "not a binary digit",
+                    };
+                    return Err(parse_error(line_number, column + i, group, reason));
+                }
+            }
+        }
+        if digits_read != 0 {
+            return Err(parse_error(line_number, column, group, "incomplete byte"));
+        }
+
+        if bytes.len() > columns {
+            return Err(parse_error(
+                line_number,
+                column,
+                group,
+                &format!("more than {} bytes on a line", columns),
+            ));
+        }
+    }
+
+    Ok(Some((offset, bytes)))
+}
+
+/// Reads a hex dump in xxd's regular format from `inhandle` and writes the binary it
+/// describes to `target`. Offsets are read in decimal with `options.decimal_offset`.
+/// `options.bits` selects bit dumps instead of hex dumps (written
+/// in the order given by `options.bits_lsb_first`, with or without nibble separators), and
+/// `options.is_little_endian` undoes the byte swapping within each group of a -e dump.
+///
+/// Every line starts with the offset its bytes belong at, followed by up to `options.cols`
+/// bytes. Lines that can't be parsed are skipped, as are bytes that can't be parsed,
+/// unless `options.strict` is set, in which case they're reported along with offsets that
+/// go backwards. Offsets are displaced by `options.seek`. In strict mode the whole dump is
+/// parsed before anything is written, so a malformed dump leaves the output untouched.
+///
+/// A line that is just a '*' stands for the rows -a or -S left out because they repeat the
+/// row before them, so the gap up to the next line is filled with copies of that row.
+fn revert_regular(
+    inhandle: Box<dyn std::io::Read>,
+    target: RevertTarget,
+    options: Options,
+) -> Result<(), RxdError> {
+    let (encoding, columns) = if options.bits {
+        let encoding = ByteEncoding::Bits {
+            lsb_first: options.bits_lsb_first,
+        };
+        (encoding, options.cols.unwrap_or(6))
+    } else {
+        (ByteEncoding::Hex, options.cols.unwrap_or(16))
+    };
+
+    let group_size = options.group_size();
+
+    let mut reader = std::io::BufReader::with_capacity(8192, inhandle);
+    let mut writer = RevertWriter::new(target, options.seek, options.strict);
+    let mut line: Vec<u8> = Vec::new();
+    let mut line_number = 0;
+    // how far into the input we've read, for read errors
+    let mut input_offset: u64 = 0;
+    // where the previous line's bytes ended, to catch offsets going backwards
+    let mut previous_end: u64 = 0;
+    // the bytes of the previous line, and whether a '*' asked for them to be repeated
+    let mut previous_row: Vec<u8> = Vec::new();
+    let mut repeat_previous = false;
+
+    loop {
+        line.clear();
+        let bytes_read =
+            reader
+                .read_until(b'\n', &mut line)
+                .map_err(|error| RxdError::ReadError {
+                    offset: input_offset,
+                    error,
+                })?;
+        if bytes_read == 0 {
+            break;
+        }
+        input_offset += bytes_read as u64;
+        line_number += 1;
+
+        if line.trim_ascii() == b"*" {
+            repeat_previous = true;
+            continue;
+        }
+
+        let parsed = if options.strict {
+            parse_line_strict(
+                &line,
+                line_number,
+                columns,
+                encoding,
+                options.decimal_offset,
+            )?
+        } else {
+            parse_line(&line, columns, encoding, options.decimal_offset)
+        };
+
+        if let Some((offset, mut bytes)) = parsed
+            && !bytes.is_empty()
+        {
+            let offset_text = line.split(|c| *c == b':').next().unwrap_or(&[]);
+            let end = match offset.checked_add(bytes.len() as u64) {
+                Some(end) => end,
+                None if options.strict => {
+                    return Err(parse_error(
+                        line_number,
+                        1,
+                        offset_text,
+                        "the bytes run past the largest possible offset",
+                    ));
+                }
+                // the bytes can't be written anywhere, so skip the line like other garbage
+                None => continue,
+            };
+
+            if options.strict && offset < previous_end {
+                return Err(parse_error(
+                    line_number,
+                    1,
+                    offset_text,
+                    &if options.decimal_offset {
+                        format!("offset goes backwards from {}", previous_end)
+                    } else {
+                        format!("offset goes backwards from {:x}", previous_end)
+                    },
+                ));
+            }
+
+            if options.is_little_endian {
+                // every line starts a new group, and the last group on a line may be short
+                for group in bytes.chunks_mut(group_size) {
+                    group.reverse();
+                }
+            }
+
+            if repeat_previous && !previous_row.is_empty() {
+                let mut position = previous_end;
+                while position < offset {
+                    let count = std::cmp::min(offset - position, previous_row.len() as u64);
+                    writer.write_at(position, &previous_row[..count as usize])?;
+                    position += count;
+                }
+            }
+            repeat_previous = false;
+
+            writer.write_at(offset, &bytes)?;
+            previous_end = end;
+            previous_row = bytes;
+        }
+    }
+
+    writer.finish()
+}
+
+/// Reads a plain (postscript) hex dump from `inhandle` and writes the binary it describes
+/// to `target`.
+///
+/// The input is treated as one continuous stream of hex digits, so lines can be of any
+/// length and anything that isn't a hex digit (whitespace, punctuation, ...) is ignored.
+/// If the input has an odd number of hex digits, the trailing nibble is dropped.
+/// With `options.strict`, only whitespace is allowed between hex digits, a trailing nibble
+/// is an error, and nothing is written unless the whole dump is valid. The bytes are
+/// written starting at `options.seek`.
+fn revert_plain(
+    mut inhandle: Box<dyn std::io::Read>,
+    target: RevertTarget,
+    options: Options,
+) -> Result<(), RxdError> {
+    let mut inbuf = [0u8; 8192];
+    let mut outbuf = Vec::with_capacity(inbuf.len() / 2);
+    let mut writer = RevertWriter::new(target, options.seek, options.strict);
+    let mut high_nibble: Option<u8> = None;
+    let mut offset: u64 = 0;
+    let mut input_offset: u64 = 0;
+    // 1-based position in the input, for diagnostics in strict mode
+    let (mut line, mut column) = (1, 0);
+
+    loop {
+        let bytes_read = inhandle
+            .read(&mut inbuf)
+            .map_err(|error| RxdError::ReadError {
+                offset: input_offset,
+                error,
+            })?;
+        input_offset += bytes_read as u64;
+        if bytes_read == 0 {
+            break;
+        }
+
+        outbuf.clear();
+        for c in &inbuf[..bytes_read] {
+            column += 1;
+            match hex_value(*c) {
+                Some(digit) => match high_nibble.take() {
+                    Some(high) => outbuf.push(high << 4 | digit),
+                    None => high_nibble = Some(digit),
+                },
+                None if *c == b'\n' => {
+                    line += 1;
+                    column = 0;
+                }
+                None if options.strict && !c.is_ascii_whitespace() => {
+                    return Err(parse_error(line, column, &[*c], "not a hex digit"));
+                }
+                None => {}
+            }
+        }
+
+        writer.write_at(offset, &outbuf)?;
+        offset += outbuf.len() as u64;
+    }
+
+    if options.strict && high_nibble.is_some() {
+        return Err(parse_error(line, column, b"", "odd number of hex digits"));
+    }
+
+    writer.finish()
+}
+
+/// Replaces every C comment in `source` with a single space.
+fn strip_c_comments(source: &str) -> String {
+    let mut stripped = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while let Some(start) = rest.find('/') {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("//") {
+            rest = rest.find('\n').map_or("", |end| &rest[end..]);
+            stripped.push(' ');
+        } else if rest.starts_with("/*") {
+            rest = rest[2..].find("*/").map_or("", |end| &rest[end + 4..]);
+            stripped.push(' ');
+        } else {
+            stripped.push('/');
+            rest = &rest[1..];
+        }
+    }
+
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Parses a C integer literal: decimal, hex (0x) or octal (leading 0), with any
+/// combination of u/U/l/L suffixes.
+fn parse_c_integer(token: &str) -> Option<u64> {
+    let token = token.trim_end_matches(['u', 'U', 'l', 'L']);
+
+    if let Some(hex) = token.strip_prefix("0x").or(token.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if token.len() > 1 && token.starts_with('0') {
+        u64::from_str_radix(&token[1..], 8).ok()
+    } else {
+        token.parse::<u64>().ok()
+    }
+}
+
+/// Finds the first array initializer in `source` (the first one named `name`, if given),
+/// returning the array's name and the text between its braces.
+fn find_c_array<'a>(source: &'a str, name: Option<&str>) -> Option<(&'a str, &'a str)> {
+    let is_identifier = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut search_from = 0;
+
+    while let Some(brace) = source[search_from..].find('{').map(|b| b + search_from) {
+        search_from = brace + 1;
+
+        // looking for `<array_name>[<anything>] = {`
+        let declaration = match source[..brace].trim_end().strip_suffix('=') {
+            Some(declaration) => declaration.trim_end(),
+            None => continue,
+        };
+        let declaration = match declaration.strip_suffix(']') {
+            Some(declaration) => declaration,
+            None => continue,
+        };
+        let declaration = match declaration.rfind('[') {
+            Some(bracket) => declaration[..bracket].trim_end(),
+            None => continue,
+        };
+        let array_name_start = declaration
+            .rfind(|c: char| !is_identifier(c))
+            .map_or(0, |i| i + 1);
+        let array_name = &declaration[array_name_start..];
+
+        if array_name.is_empty() || name.is_some_and(|name| !name.eq_ignore_ascii_case(array_name))
+        {
+            continue;
+        }
+
+        let body_end = source[brace..].find('}')? + brace;
+        return Some((array_name, &source[brace + 1..body_end]));
+    }
+
+    None
+}
+
+/// Finds the value assigned to `<array_name>_len` in `source`, if there is one. Returns the
+/// name as it is spelled in `source` (`-C` output has `<ARRAY_NAME>_LEN`) and the value.
+fn find_c_array_length<'a>(source: &'a str, array_name: &str) -> Option<(&'a str, u64)> {
+    let length_name = format!("{}_len", array_name).to_ascii_lowercase();
+    let lowercase_source = source.to_ascii_lowercase();
+    let mut search_from = 0;
+
+    while let Some(start) = lowercase_source[search_from..].find(&length_name) {
+        let start = start + search_from;
+        let end = start + length_name.len();
+        search_from = end;
+
+        // make sure we didn't match the middle of a longer identifier
+        let before = lowercase_source[..start].chars().next_back();
+        let after = lowercase_source[end..].chars().next();
+        if before.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+            || after.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            continue;
+        }
+
+        if let Some(value) = source[end..].trim_start().strip_prefix('=') {
+            let value = value.split(';').next().unwrap_or("").trim();
+            return parse_c_integer(value).map(|length| (&source[start..end], length));
+        }
+    }
+
+    None
+}
+
+/// Reads C source containing an array initializer, like the output of `include_format`,
+/// and writes the bytes in the array to `target`.
+///
+/// Comments, whitespace and a trailing comma are all allowed, and the array's name doesn't
+/// matter unless `options.include_name` is given, in which case that array is picked out.
+/// If the source also assigns `<array_name>_len`, it has to agree with the number of bytes
+/// found in the array.
+fn revert_include(
+    mut inhandle: Box<dyn std::io::Read>,
+    target: RevertTarget,
+    options: Options,
+) -> Result<(), RxdError> {
+    let mut raw_source = Vec::new();
+    inhandle
+        .read_to_end(&mut raw_source)
+        .map_err(|error| RxdError::ReadError {
+            offset: raw_source.len() as u64,
+            error,
+        })?;
+    let source = strip_c_comments(&String::from_utf8_lossy(&raw_source));
+
+    let (array_name, body) =
+        find_c_array(&source, options.include_name.as_deref()).ok_or_else(|| {
+            match options.include_name {
+                Some(ref name) => {
+                    RxdError::InvalidDump(format!("Sorry, could not find the array {}.", name))
+                }
+                None => {
+                    RxdError::InvalidDump("Sorry, could not find an array initializer.".to_owned())
+                }
+            }
+        })?;
+
+    let mut bytes = Vec::with_capacity(body.len() / 6);
+    for token in body
+        .split(',')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+    {
+        match parse_c_integer(token).and_then(|value| u8::try_from(value).ok()) {
+            Some(byte) => bytes.push(byte),
+            None => {
+                return Err(RxdError::InvalidDump(format!(
+                    "Sorry, {} in {} is not a byte.",
+                    token, array_name
+                )));
+            }
+        }
+    }
+
+    if let Some((length_name, length)) = find_c_array_length(&source, array_name)
+        && length != bytes.len() as u64
+    {
+        return Err(RxdError::InvalidDump(format!(
+            "Sorry, {} is {} but {} has {} bytes.",
+            length_name,
+            length,
+            array_name,
+            bytes.len()
+        )));
+    }
+
+    let mut writer = RevertWriter::new(target, options.seek, false);
+    writer.write_at(0, &bytes)?;
+    writer.finish()
+}
+
+/// Converts the hex dump read from `inhandle` back into binary and writes it to `target`.
+/// `options.postscript_style` and `options.include_format` select between plain hex dumps,
+/// C include files and regular hex dumps.
 pub fn revert(
     inhandle: Box<dyn std::io::Read>,
-    outhandle: Box<dyn std::io::Write>,
+    target: RevertTarget,
     options: Options,
-) {
-    /* parsing outline:
-     * the first token is considered as an offset. This needs to be encoded in hex.
-     * If we're writing to a file, seek to the offset.
-     *
-     * Read the `options.cols * options.group_size` bytes separated by a singular space.
-     * At this point, move on to the next line.
-     *
-     * If the parse of a line offset fails, move on to the next line offset token.
-     * If the parse of a byte fails, move on to the next byte token.
-     */
+) -> Result<(), RxdError> {
+    if options.postscript_style {
+        revert_plain(inhandle, target, options)
+    } else if options.include_format {
+        revert_include(inhandle, target, options)
+    } else {
+        revert_regular(inhandle, target, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// reverts `dump` into a temporary file holding `existing`, and returns the result along
+    /// with what the file holds afterwards.
+    fn revert_over(
+        existing: &[u8],
+        dump: &str,
+        options: Options,
+    ) -> (Result<(), RxdError>, Vec<u8>) {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "rxd-revert-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, existing).unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+
+        let input = Box::new(std::io::Cursor::new(dump.as_bytes().to_vec()));
+        let result = revert(input, RevertTarget::File(file), options);
+        let output = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        (result, output)
+    }
+
+    /// reverts `dump` into a fresh temporary file and returns the bytes written to it.
+    fn revert_to_vec(dump: &str, options: Options) -> Result<Vec<u8>, RxdError> {
+        let (result, output) = revert_over(b"", dump, options);
+        result.map(|()| output)
+    }
+
+    fn strict() -> Options {
+        Options {
+            strict: true,
+            ..Options::default()
+        }
+    }
+
+    /// the line, column and reason of a strict mode error.
+    fn diagnostic(result: Result<Vec<u8>, RxdError>) -> (usize, usize, String) {
+        match result {
+            Err(RxdError::ParseError {
+                line,
+                column,
+                reason,
+                ..
+            }) => (line, column, reason),
+            Err(e) => panic!("expected a parse error, got {e}"),
+            Ok(bytes) => panic!("expected a parse error, got {bytes:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_line_skips_the_ascii_column() {
+        let line = b"00000010: 4865 6c6c 6f                                Hello";
+        assert_eq!(
+            parse_line(line, 16, ByteEncoding::Hex, false),
+            Some((0x10, b"Hello".to_vec()))
+        );
+    }
+
+    #[test]
+    fn parse_line_reads_decimal_offsets_and_bits() {
+        let line = b"00000012: 01001000 01101001  Hi";
+        assert_eq!(
+            parse_line(line, 6, ByteEncoding::Bits { lsb_first: false }, true),
+            Some((12, b"Hi".to_vec()))
+        );
+    }
+
+    #[test]
+    fn parse_line_rejects_offsets_that_overflow() {
+        let line = b"10000000000000041: 41  A";
+        assert_eq!(parse_line(line, 16, ByteEncoding::Hex, false), None);
+    }
+
+    #[test]
+    fn parse_line_needs_an_offset() {
+        assert_eq!(parse_line(b"*", 16, ByteEncoding::Hex, false), None);
+    }
+
+    #[test]
+    fn strict_reverts_regular_output() {
+        let dump = "00000000: 4865 6c6c 6f20 576f 726c 6421            Hello World!\n";
+        assert_eq!(revert_to_vec(dump, strict()).unwrap(), b"Hello World!");
+    }
+
+    #[test]
+    fn strict_reports_a_missing_colon() {
+        let dump = "00000000: 4142  AB\n00000002 4344  CD\n";
+        assert_eq!(
+            diagnostic(revert_to_vec(dump, strict())),
+            (2, 1, "missing ':' after the offset".to_owned())
+        );
+    }
+
+    #[test]
+    fn strict_reports_the_column_of_a_bad_offset_digit() {
+        assert_eq!(
+            diagnostic(revert_to_vec("000g0000: 41  A\n", strict())),
+            (1, 4, "malformed offset".to_owned())
+        );
+    }
+
+    #[test]
+    fn strict_reports_the_column_of_a_bad_digit() {
+        assert_eq!(
+            diagnostic(revert_to_vec("00000000: 4142 43x4  ABC.\n", strict())),
+            (1, 18, "not a hex digit".to_owned())
+        );
+    }
+
+    #[test]
+    fn strict_reports_an_incomplete_byte() {
+        assert_eq!(
+            diagnostic(revert_to_vec("00000000: 4142 434  ABC\n", strict())),
+            (1, 16, "incomplete byte".to_owned())
+        );
+    }
+
+    #[test]
+    fn strict_leaves_the_output_alone_on_errors() {
+        let dump = "00000000: 4142  AB\n00000002: 43zz  C.\n";
+        let (result, output) = revert_over(b"xxxx", dump, strict());
+        assert!(result.is_err());
+        assert_eq!(output, b"xxxx");
+
+        let options = Options {
+            postscript_style: true,
+            ..strict()
+        };
+        let (result, output) = revert_over(b"xxxx", "4142\n43z\n", options);
+        assert!(result.is_err());
+        assert_eq!(output, b"xxxx");
+    }
+
+    #[test]
+    fn strict_reports_offsets_going_backwards() {
+        let dump = "00000010: 4142  AB\n00000011: 4344  CD\n";
+        assert_eq!(
+            diagnostic(revert_to_vec(dump, strict())),
+            (2, 1, "offset goes backwards from 12".to_owned())
+        );
+    }
+
+    #[test]
+    fn strict_reports_bytes_past_the_largest_offset() {
+        assert_eq!(
+            diagnostic(revert_to_vec("ffffffffffffffff: 4142  AB\n", strict())),
+            (
+                1,
+                1,
+                "the bytes run past the largest possible offset".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn lenient_revert_skips_bytes_past_the_largest_offset() {
+        let dump = "ffffffffffffffff: 4142  AB\n00000000: 43  C\n";
+        assert_eq!(revert_to_vec(dump, Options::default()).unwrap(), b"C");
+    }
+
+    #[test]
+    fn strict_accepts_autoskip_stars() {
+        let dump = "00000000: 0000  ..\n*\n00000006: 0041  .A\n";
+        assert_eq!(revert_to_vec(dump, strict()).unwrap(), b"\0\0\0\0\0\0\0A");
+    }
+
+    #[test]
+    fn stars_repeat_the_row_before_them() {
+        let dump = "00000000: ffff  ..\n*\n00000006: ff41  .A\n";
+        assert_eq!(
+            revert_to_vec(dump, strict()).unwrap(),
+            b"\xff\xff\xff\xff\xff\xff\xffA"
+        );
+        assert_eq!(
+            revert_to_vec(dump, Options::default()).unwrap(),
+            b"\xff\xff\xff\xff\xff\xff\xffA"
+        );
+    }
+
+    #[test]
+    fn little_endian_groups_default_to_four_bytes() {
+        let options = Options {
+            is_little_endian: true,
+            ..Options::default()
+        };
+        let dump = "00000000: 64636261 6665  abcdef\n";
+        assert_eq!(revert_to_vec(dump, options).unwrap(), b"abcdef");
+    }
+
+    #[test]
+    fn plain_ignores_everything_but_hex_digits() {
+        let options = Options {
+            postscript_style: true,
+            ..Options::default()
+        };
+        assert_eq!(revert_to_vec("41 42,\n43-44\n4", options).unwrap(), b"ABCD");
+    }
+
+    #[test]
+    fn strict_plain_reports_an_odd_number_of_digits() {
+        let options = Options {
+            postscript_style: true,
+            strict: true,
+            ..Options::default()
+        };
+        let (line, _, reason) = diagnostic(revert_to_vec("4142\n434", options));
+        assert_eq!(line, 2);
+        assert_eq!(reason, "odd number of hex digits");
+    }
+
+    #[test]
+    fn strip_c_comments_keeps_the_code() {
+        assert_eq!(
+            strip_c_comments("int a /* one */ = 1; // two\nint b;"),
+            "int a   = 1;  \nint b;"
+        );
+    }
+
+    #[test]
+    fn find_c_array_picks_the_named_array() {
+        let source = "unsigned char a[] = {1};\nunsigned char b[] = { 2, 3 };";
+        assert_eq!(find_c_array(source, None), Some(("a", "1")));
+        assert_eq!(find_c_array(source, Some("b")), Some(("b", " 2, 3 ")));
+        assert_eq!(find_c_array(source, Some("c")), None);
+    }
+
+    #[test]
+    fn find_c_array_length_matches_whole_identifiers() {
+        let source = "unsigned int FOO_LEN_2 = 7;\nunsigned int FOO_LEN = 3;";
+        assert_eq!(find_c_array_length(source, "foo"), Some(("FOO_LEN", 3)));
+        assert_eq!(find_c_array_length(source, "bar"), None);
+    }
+
+    #[test]
+    fn include_reports_a_mismatched_length_by_its_name() {
+        let options = Options {
+            include_format: true,
+            ..Options::default()
+        };
+        let source = "unsigned char FOO[] = {0x01, 0x02};\nunsigned int FOO_LEN = 3;\n";
+        match revert_to_vec(source, options) {
+            Err(RxdError::InvalidDump(message)) => {
+                assert_eq!(message, "Sorry, FOO_LEN is 3 but FOO has 2 bytes.")
+            }
+            other => panic!("expected a length mismatch, got {other:?}"),
+        }
+    }
 }
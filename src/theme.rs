@@ -0,0 +1,235 @@
+use crate::RxdError;
+use crate::format::Color;
+use std::{env, fs};
+
+/// The theme every other theme starts from, matching rxd's original colors.
+const DEFAULT_THEME: &str = "
+offset = cyan
+*      = red
+21-7e  = green
+09 0a 20 = yellow
+ff     = blue
+00     = white
+";
+
+/// A theme that avoids telling bytes apart by red and green alone.
+const COLORBLIND_THEME: &str = "
+offset = 250
+*      = 208
+21-7e  = 75
+09 0a 20 = 227
+ff     = 177
+00     = 244
+";
+
+/// The escape sequences used to colorize the output of `regular_format`.
+///
+/// Every byte value belongs to a class, and every class has its own color. Byte classes
+/// are built from rules of the form `<selectors> = <color>`, one per line (or separated
+/// by ';'), where later rules override earlier ones:
+///
+/// * a selector is a byte in hex (`0a` or `0x0a`), an inclusive range (`20-7e`), `*` for
+///   every byte, or `offset` for the offset column. Selectors are separated by spaces or ','.
+/// * a color is one of black, red, green, yellow, blue, magenta, cyan or white, a number
+///   from the 256-color palette (`208`), or a truecolor value (`#ff8800`).
+///
+/// Lines starting with '#' are comments.
+#[derive(Clone)]
+pub struct Theme {
+    /// the class each byte value belongs to
+    classes: [u8; 256],
+    /// the escape sequence for each class
+    escapes: Vec<String>,
+    offset_escape: String,
+}
+
+/// Parses a color in a theme.
+fn parse_color(text: &str) -> Option<Color> {
+    let color = match text.to_ascii_lowercase().as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "white" => Color::White,
+        hex if hex.starts_with('#') && hex.len() == 7 => {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            Color::Rgb(channel(1)?, channel(3)?, channel(5)?)
+        }
+        index => Color::Ansi256(index.parse().ok()?),
+    };
+    Some(color)
+}
+
+/// Parses a byte in hex, with or without a leading 0x.
+fn parse_byte(text: &str) -> Option<u8> {
+    let text = text.strip_prefix("0x").unwrap_or(text);
+    if text.is_empty() || text.len() > 2 {
+        return None;
+    }
+    u8::from_str_radix(text, 16).ok()
+}
+
+impl Theme {
+    /// Builds a theme from the rules in `spec`, applied on top of the default theme.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut theme = Theme {
+            classes: [0; 256],
+            escapes: vec![String::new()],
+            offset_escape: String::new(),
+        };
+        theme.apply(DEFAULT_THEME)?;
+        theme.apply(spec)?;
+        Ok(theme)
+    }
+
+    fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for (line_number, line) in spec.lines().enumerate() {
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            for rule in line.split(';') {
+                if rule.trim().is_empty() {
+                    continue;
+                }
+
+                let (selectors, color) = rule.split_once('=').ok_or_else(|| {
+                    format!("line {}: expected '<bytes> = <color>'", line_number + 1)
+                })?;
+                let color = color.trim();
+                let escape = parse_color(color)
+                    .ok_or_else(|| format!("line {}: {} is not a color", line_number + 1, color))?
+                    .ansi()
+                    .into_owned();
+
+                let class = self.escapes.len() as u8;
+                let mut uses_class = false;
+
+                for selector in selectors
+                    .split([' ', '\t', ','])
+                    .filter(|selector| !selector.is_empty())
+                {
+                    let range = match selector {
+                        "offset" => {
+                            self.offset_escape = escape.clone();
+                            continue;
+                        }
+                        "*" => Some((0x00, 0xff)),
+                        _ => match selector.split_once('-') {
+                            Some((low, high)) => parse_byte(low).zip(parse_byte(high)),
+                            None => parse_byte(selector).map(|byte| (byte, byte)),
+                        },
+                    };
+
+                    let (low, high) = range.ok_or_else(|| {
+                        format!(
+                            "line {}: {} is not a byte or a range",
+                            line_number + 1,
+                            selector
+                        )
+                    })?;
+                    for byte in low..=high {
+                        self.classes[byte as usize] = class;
+                    }
+                    uses_class = true;
+                }
+
+                if uses_class {
+                    if self.escapes.len() > u8::MAX as usize {
+                        return Err("too many rules in the theme".to_owned());
+                    }
+                    self.escapes.push(escape);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads the theme named by the environment, or the default theme.
+    ///
+    /// RXD_THEME can hold the name of a built-in theme (`default` or `colorblind`) or the
+    /// rules of a theme. Otherwise, the rules are read from the file named by RXD_THEME_FILE,
+    /// or from `$XDG_CONFIG_HOME/rxd/theme` (falling back to `~/.config/rxd/theme`) if it exists.
+    pub fn load() -> Result<Self, RxdError> {
+        if let Ok(spec) = env::var("RXD_THEME") {
+            let spec = match spec.trim() {
+                "default" | "" => "",
+                "colorblind" => COLORBLIND_THEME,
+                _ => spec.as_str(),
+            };
+            return Theme::parse(spec).map_err(|e| RxdError::Message(format!("RXD_THEME: {}", e)));
+        }
+
+        let (path, required) = match env::var_os("RXD_THEME_FILE") {
+            Some(path) => (Some(path.into()), true),
+            None => {
+                let config_home = env::var_os("XDG_CONFIG_HOME")
+                    .map(std::path::PathBuf::from)
+                    .or_else(|| {
+                        env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".config"))
+                    });
+                (config_home.map(|dir| dir.join("rxd").join("theme")), false)
+            }
+        };
+
+        let spec = match path {
+            Some(ref path) => match fs::read_to_string(path) {
+                Ok(spec) => spec,
+                Err(e) if required || e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(RxdError::Message(format!(
+                        "could not read theme {}: {}",
+                        path.display(),
+                        e
+                    )));
+                }
+                Err(_) => String::new(),
+            },
+            None => String::new(),
+        };
+
+        Theme::parse(&spec).map_err(|e| {
+            RxdError::Message(format!("{}: {}", path.unwrap_or_default().display(), e))
+        })
+    }
+
+    /// The class `byte` belongs to; bytes in the same class share a color.
+    pub fn class(&self, byte: u8) -> u8 {
+        self.classes[byte as usize]
+    }
+
+    /// The escape sequence for a class returned by `class`.
+    pub fn escape(&self, class: u8) -> &str {
+        &self.escapes[class as usize]
+    }
+
+    /// The escape sequence for the offset column.
+    pub fn offset_escape(&self) -> &str {
+        &self.offset_escape
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_applies_rules_over_the_default_theme() {
+        let theme = Theme::parse("# comment\n41-43 = #ff8800; offset = 208").unwrap();
+        assert_eq!(theme.escape(theme.class(0x42)), "\u{1b}[38;2;255;136;0m");
+        assert_eq!(theme.offset_escape(), "\u{1b}[38;5;208m");
+        // bytes the spec doesn't mention keep their default colors
+        assert_eq!(theme.escape(theme.class(0x00)), Color::White.ansi());
+        assert_eq!(theme.escape(theme.class(0x44)), Color::Green.ansi());
+    }
+
+    #[test]
+    fn parse_rejects_bad_rules() {
+        assert!(Theme::parse("zz = red").is_err());
+        assert!(Theme::parse("00 = mauve").is_err());
+        assert!(Theme::parse("00 red").is_err());
+    }
+}